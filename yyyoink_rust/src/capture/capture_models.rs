@@ -1,37 +1,49 @@
-use std::path::PathBuf;
-
 use iced::widget::text_editor;
 
+use super::capture_record::{CaptureRecord, CaptureSection};
+use crate::utilities::index::IndexHit;
+
 pub struct Capture {
     pub search: String,
+    /// Full-text matches in capture bodies for `search`.
+    pub body_results: Vec<IndexHit>,
     pub form_topic: String,
     pub form_subject: String,
+    /// Comma or space separated tags for the capture being written.
+    pub form_tags: String,
     pub form_content: text_editor::Content,
     pub updated_file: Option<String>,
-    pub opened_capture: Option<(String, PathBuf, String)>,
+    pub opened_capture: Option<CaptureRecord>,
+    /// The opened capture's section as last read from disk, to tell external edits apart.
+    pub opened_section: Option<CaptureSection>,
     pub current_capture: String,
-    pub current_capture_timestamp: String,
     pub current_capture_file: String,
-    pub current_capture_subject: String,
     pub before: String,
     pub after: String,
+    /// Topic the opened capture is being moved to.
+    pub move_topic: String,
+    /// Only captures carrying this tag are listed in the sidebar.
+    pub tag_filter: Option<String>,
 }
 
 impl Capture {
     pub fn new() -> Self {
         Self {
             search: String::new(),
+            body_results: Vec::new(),
             form_topic: String::new(),
             form_subject: String::new(),
+            form_tags: String::new(),
             form_content: text_editor::Content::new(),
             updated_file: None,
             opened_capture: None,
+            opened_section: None,
             current_capture: "Editor..".to_string(),
-            current_capture_timestamp: String::new(),
             current_capture_file: String::new(),
-            current_capture_subject: String::new(),
             before: String::new(),
             after: String::new(),
+            move_topic: String::new(),
+            tag_filter: None,
         }
     }
 }
@@ -0,0 +1,1076 @@
+use chrono::prelude::*;
+use chrono::{LocalResult, SecondsFormat, TimeDelta};
+use std::collections::HashSet;
+use std::fmt;
+use ulid::Ulid;
+
+const HEADER_PREFIX: &str = "<!--yoink";
+const HEADER_DELIMITER: &str = "::::";
+const HEADER_SUFFIX: &str = "-->";
+const ID_KEY: &str = "id=";
+const TAGS_KEY: &str = "tags=";
+const TAG_SEPARATOR: char = ',';
+/// How the characters that could break a header are written inside topic and subject fields.
+const FIELD_ESCAPES: [(char, &str); 5] = [
+    ('%', "%25"),
+    ('\n', "%0A"),
+    ('\r', "%0D"),
+    (':', "%3A"),
+    ('>', "%3E"),
+];
+
+/// Timestamps written before they carried an offset, e.g. `2025-04-01 09:00:00`.
+const LEGACY_TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// The timezone a legacy timestamp, written without an offset, is taken to be in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AssumedZone {
+    /// The system timezone, including its daylight saving rules.
+    #[default]
+    Local,
+    Fixed(FixedOffset),
+}
+
+impl AssumedZone {
+    /// Parses `local`, `utc` or an offset such as `+02:00`.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "local" => Some(AssumedZone::Local),
+            "utc" | "z" => FixedOffset::east_opt(0).map(AssumedZone::Fixed),
+            offset => offset.parse().ok().map(AssumedZone::Fixed),
+        }
+    }
+
+    /// Places a wall clock time in this zone. A time skipped by a daylight saving change is
+    /// read with the offset in effect just before the change.
+    pub fn resolve(&self, time: NaiveDateTime) -> DateTime<FixedOffset> {
+        match self {
+            AssumedZone::Local => match Local.from_local_datetime(&time) {
+                LocalResult::Single(resolved) | LocalResult::Ambiguous(resolved, _) => {
+                    resolved.fixed_offset()
+                }
+                LocalResult::None => {
+                    let offset = Local
+                        .offset_from_utc_datetime(&(time - TimeDelta::hours(1)))
+                        .fix();
+                    offset.from_utc_datetime(&(time - offset))
+                }
+            },
+            AssumedZone::Fixed(offset) => offset.from_utc_datetime(&(time - *offset)),
+        }
+    }
+}
+
+/// The `<!--yoink::::timestamp::::topic::::subject-->` line that opens every capture.
+/// Optional fields follow the subject as `::::key=value`, e.g. `::::id=01J…` or
+/// `::::tags=rust,ideas`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureHeader {
+    pub timestamp: String,
+    pub topic: String,
+    pub subject: String,
+    pub id: Option<String>,
+    pub tags: Vec<String>,
+}
+
+impl CaptureHeader {
+    pub fn new(topic: &str, subject: &str) -> Self {
+        Self {
+            timestamp: Self::timestamp_now(),
+            topic: topic.to_string(),
+            subject: subject.to_string(),
+            id: Some(Self::new_id()),
+            tags: Vec::new(),
+        }
+    }
+
+    /// The current time as RFC 3339 with the local offset, e.g. `2025-04-01T09:00:00+02:00`.
+    pub fn timestamp_now() -> String {
+        Local::now().to_rfc3339_opts(SecondsFormat::Secs, false)
+    }
+
+    /// When the capture was made. Reads RFC 3339 timestamps and legacy ones, which are taken
+    /// to be in `zone`.
+    pub fn time(&self, zone: AssumedZone) -> Option<DateTime<FixedOffset>> {
+        DateTime::parse_from_rfc3339(&self.timestamp)
+            .ok()
+            .or_else(|| {
+                NaiveDateTime::parse_from_str(&self.timestamp, LEGACY_TIMESTAMP_FORMAT)
+                    .ok()
+                    .map(|time| zone.resolve(time))
+            })
+    }
+
+    /// Rewrites a legacy timestamp as RFC 3339 in `zone`. Returns whether it changed.
+    pub fn migrate_timestamp(&mut self, zone: AssumedZone) -> bool {
+        if DateTime::parse_from_rfc3339(&self.timestamp).is_ok() {
+            return false;
+        }
+        match self.time(zone) {
+            Some(time) => {
+                self.timestamp = time.to_rfc3339_opts(SecondsFormat::Secs, false);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Orders captures by the moment they were made, whatever offset they were written with.
+    /// Legacy timestamps are read in `zone`. Timestamps that do not parse sort first, by their
+    /// text.
+    pub fn sort_key(&self, zone: AssumedZone) -> (Option<DateTime<Utc>>, String) {
+        (
+            self.time(zone).map(|time| time.with_timezone(&Utc)),
+            self.timestamp.clone(),
+        )
+    }
+
+    pub fn new_id() -> String {
+        Ulid::new().to_string()
+    }
+
+    /// Gives headers written before IDs existed an ID of their own.
+    pub fn ensure_id(&mut self) {
+        if self.id.is_none() {
+            self.id = Some(Self::new_id());
+        }
+    }
+
+    /// Parses a single line, returning `None` if it is not a capture header.
+    pub fn parse(line: &str) -> Option<Self> {
+        let inner = line
+            .strip_prefix(HEADER_PREFIX)?
+            .strip_suffix(HEADER_SUFFIX)?
+            .strip_prefix(HEADER_DELIMITER)?;
+        let mut parts = inner.split(HEADER_DELIMITER);
+
+        let mut header = match (parts.next(), parts.next(), parts.next()) {
+            (Some(timestamp), Some(topic), Some(subject)) => Self {
+                timestamp: timestamp.to_string(),
+                topic: unescape_field(topic),
+                subject: unescape_field(subject),
+                id: None,
+                tags: Vec::new(),
+            },
+            _ => return None,
+        };
+
+        let mut has_tags = false;
+        for part in parts {
+            if let Some(id) = part.strip_prefix(ID_KEY) {
+                if header.id.is_some() || id.is_empty() {
+                    return None;
+                }
+                header.id = Some(id.to_string());
+            } else if let Some(tags) = part.strip_prefix(TAGS_KEY) {
+                if has_tags {
+                    return None;
+                }
+                has_tags = true;
+                header.tags = parse_tags(tags);
+            } else {
+                return None;
+            }
+        }
+
+        Some(header)
+    }
+
+    /// Compares by ID when both headers carry one, otherwise by every field.
+    pub fn is_same_capture(&self, other: &CaptureHeader) -> bool {
+        match (&self.id, &other.id) {
+            (Some(id), Some(other_id)) => id == other_id,
+            _ => self == other,
+        }
+    }
+
+    /// The topic file this capture belongs in, e.g. `_work.md`.
+    pub fn file_name(&self) -> String {
+        format!("_{}.md", self.topic)
+    }
+}
+
+impl fmt::Display for CaptureHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}{}{}{}{}{}",
+            HEADER_PREFIX,
+            HEADER_DELIMITER,
+            self.timestamp,
+            HEADER_DELIMITER,
+            escape_field(&self.topic),
+            HEADER_DELIMITER,
+            escape_field(&self.subject),
+        )?;
+        if let Some(id) = &self.id {
+            write!(f, "{}{}{}", HEADER_DELIMITER, ID_KEY, id)?;
+        }
+        if !self.tags.is_empty() {
+            let tags = self.tags.join(&TAG_SEPARATOR.to_string());
+            write!(f, "{}{}{}", HEADER_DELIMITER, TAGS_KEY, tags)?;
+        }
+        write!(f, "{}", HEADER_SUFFIX)
+    }
+}
+
+/// Escapes a topic or subject so it stays one field of a one-line header. `%`, line breaks, the
+/// `>` of a `-->` and every `:` at the edge of the field or next to another `:` are written as
+/// `%XX`; anything else is kept readable.
+pub fn escape_field(field: &str) -> String {
+    let chars: Vec<char> = field.chars().collect();
+    let mut escaped = String::with_capacity(field.len());
+
+    for (i, &c) in chars.iter().enumerate() {
+        let needs_escape = match c {
+            '%' | '\n' | '\r' => true,
+            ':' => i == 0 || i + 1 == chars.len() || chars[i - 1] == ':' || chars[i + 1] == ':',
+            '>' => i >= 2 && chars[i - 2..i] == ['-', '-'],
+            _ => false,
+        };
+        match FIELD_ESCAPES
+            .iter()
+            .find(|(raw, _)| needs_escape && *raw == c)
+        {
+            Some((_, code)) => escaped.push_str(code),
+            None => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Reverses `escape_field`. A `%` that does not start a known escape is kept as is, so headers
+/// written before fields were escaped read back unchanged.
+pub fn unescape_field(field: &str) -> String {
+    let mut unescaped = String::with_capacity(field.len());
+    let mut rest = field;
+
+    while let Some(at) = rest.find('%') {
+        unescaped.push_str(&rest[..at]);
+        let code = rest.get(at..at + 3).map(str::to_ascii_uppercase);
+        match FIELD_ESCAPES
+            .iter()
+            .find(|(_, escape)| code.as_deref() == Some(*escape))
+        {
+            Some((raw, _)) => {
+                unescaped.push(*raw);
+                rest = &rest[at + 3..];
+            }
+            None => {
+                unescaped.push('%');
+                rest = &rest[at + 1..];
+            }
+        }
+    }
+
+    unescaped.push_str(rest);
+    unescaped
+}
+
+/// A capture as listed in the sidebar: its header plus the topic file it was read from.
+/// `tags` holds the header tags and the inline `#tags` of the body, sorted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureRecord {
+    pub header: CaptureHeader,
+    pub file_name: String,
+    pub tags: Vec<String>,
+}
+
+impl CaptureRecord {
+    pub fn new(section: &CaptureSection, file_name: &str) -> Self {
+        Self {
+            header: section.header.clone(),
+            file_name: file_name.to_string(),
+            tags: section.tags(),
+        }
+    }
+}
+
+/// Header tags together with the inline tags of `body`, sorted and without repeats.
+pub fn capture_tags(header: &CaptureHeader, body: &str) -> Vec<String> {
+    let mut tags = header.tags.clone();
+    tags.extend(inline_tags(body));
+    tags.sort();
+    tags.dedup();
+    tags
+}
+
+/// Lowercases a tag and strips a leading `#`. Returns `None` for anything that is not made of
+/// letters, digits, `-`, `_` and `/`.
+pub fn normalize_tag(raw: &str) -> Option<String> {
+    let tag = raw.trim().trim_start_matches('#').to_lowercase();
+    if tag.is_empty() || !tag.chars().all(is_tag_char) {
+        return None;
+    }
+    Some(tag)
+}
+
+/// Reads a comma or whitespace separated tag list, dropping invalid and repeated tags.
+pub fn parse_tags(input: &str) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in input
+        .split(|c: char| c == TAG_SEPARATOR || c.is_whitespace())
+        .filter_map(normalize_tag)
+    {
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags
+}
+
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '-' | '_' | '/')
+}
+
+fn is_fence(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with("```") || line.starts_with("~~~")
+}
+
+/// Byte ranges of the `#tag`s on one line, `#` included. A tag starts at the beginning of the
+/// line or after whitespace, begins with a letter and is not inside inline code, so headings,
+/// issue numbers and `#[attributes]` are left alone.
+pub fn tag_spans(line: &str) -> Vec<(usize, usize)> {
+    let mut spans: Vec<(usize, usize)> = Vec::new();
+    let mut in_code = false;
+    let mut previous: Option<char> = None;
+    let mut chars = line.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        if c == '`' {
+            in_code = !in_code;
+        } else if c == '#'
+            && !in_code
+            && previous.is_none_or(char::is_whitespace)
+            && chars.peek().is_some_and(|(_, next)| next.is_alphabetic())
+        {
+            let mut end = start + 1;
+            while let Some(&(index, next)) = chars.peek() {
+                if !is_tag_char(next) {
+                    break;
+                }
+                end = index + next.len_utf8();
+                chars.next();
+            }
+            spans.push((start, end));
+            previous = line[..end].chars().last();
+            continue;
+        }
+        previous = Some(c);
+    }
+
+    spans
+}
+
+/// The inline `#tags` of a capture body, lowercased, in order of first appearance. Fenced
+/// code blocks are skipped.
+pub fn inline_tags(body: &str) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    let mut in_fence = false;
+
+    for line in body.lines() {
+        if is_fence(line) {
+            in_fence = !in_fence;
+            continue;
+        }
+        if in_fence {
+            continue;
+        }
+        for (start, end) in tag_spans(line) {
+            let tag = line[start + 1..end].to_lowercase();
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+    }
+
+    tags
+}
+
+/// Renames tag `from` to `to` in every header and inline `#tag` of a topic file. When the
+/// capture already has `to` the two are merged. Everything else, preamble and line endings
+/// included, is kept byte-for-byte.
+pub fn rename_tag(content: &str, from: &str, to: &str) -> String {
+    let mut renamed = String::with_capacity(content.len());
+    let mut in_capture = false;
+    let mut in_fence = false;
+
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim_end_matches(['\n', '\r']);
+        let ending = &line[trimmed.len()..];
+
+        if let Some(mut header) = CaptureHeader::parse(trimmed) {
+            in_capture = true;
+            in_fence = false;
+            if header.tags.iter().any(|tag| tag == from) {
+                let mut tags: Vec<String> = Vec::new();
+                for tag in header.tags.drain(..) {
+                    let tag = if tag == from { to.to_string() } else { tag };
+                    if !tags.contains(&tag) {
+                        tags.push(tag);
+                    }
+                }
+                header.tags = tags;
+                renamed.push_str(&header.to_string());
+                renamed.push_str(ending);
+                continue;
+            }
+        } else if in_capture && is_fence(trimmed) {
+            in_fence = !in_fence;
+        } else if in_capture && !in_fence {
+            let mut last = 0;
+            for (start, end) in tag_spans(trimmed) {
+                if trimmed[start + 1..end].to_lowercase() == from {
+                    renamed.push_str(&trimmed[last..start]);
+                    renamed.push('#');
+                    renamed.push_str(to);
+                    last = end;
+                }
+            }
+            if last > 0 {
+                renamed.push_str(&trimmed[last..]);
+                renamed.push_str(ending);
+                continue;
+            }
+        }
+        renamed.push_str(line);
+    }
+
+    renamed
+}
+
+/// A header and the body that follows it, up to the next header. `text` is the exact slice of
+/// the topic file, header line and line endings included, so untouched sections are written
+/// back byte-for-byte.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureSection {
+    pub header: CaptureHeader,
+    pub text: String,
+}
+
+impl CaptureSection {
+    pub fn to_text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn tags(&self) -> Vec<String> {
+        capture_tags(&self.header, self.body())
+    }
+
+    /// The same section under another header, keeping the header line's ending and the body.
+    pub fn with_header(&self, header: CaptureHeader) -> CaptureSection {
+        CaptureSection {
+            text: format!("{}{}{}", header, self.header_ending(), self.body()),
+            header,
+        }
+    }
+
+    /// The section with an edited body under `header`. The line breaks that ended the old body,
+    /// e.g. the blank line before the next header, are kept in place of the edited body's.
+    pub fn with_body(&self, header: CaptureHeader, body: &str) -> CaptureSection {
+        let ending = match self.header_ending() {
+            "" => "\n",
+            ending => ending,
+        };
+        let old_body = self.body();
+        let separator = match old_body.trim_end_matches(['\n', '\r']).len() {
+            0 => ending,
+            len => &old_body[len..],
+        };
+        CaptureSection {
+            text: format!(
+                "{}{}{}{}",
+                header,
+                ending,
+                body.trim_end_matches(['\n', '\r']),
+                separator
+            ),
+            header,
+        }
+    }
+
+    fn header_ending(&self) -> &str {
+        let line = self.text.split_inclusive('\n').next().unwrap_or_default();
+        &line[line.trim_end_matches(['\n', '\r']).len()..]
+    }
+
+    /// Everything after the header line.
+    pub fn body(&self) -> &str {
+        match self.text.find('\n') {
+            Some(index) => &self.text[index + 1..],
+            None => "",
+        }
+    }
+}
+
+/// Splits a topic file into its capture sections. Lines before the first header are skipped.
+pub fn parse_sections(content: &str) -> Vec<CaptureSection> {
+    let mut sections: Vec<CaptureSection> = Vec::new();
+    let mut current_section: Option<CaptureSection> = None;
+
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim_end_matches(['\n', '\r']);
+        if let Some(header) = CaptureHeader::parse(trimmed) {
+            if let Some(section) = current_section.take() {
+                sections.push(section);
+            }
+            current_section = Some(CaptureSection {
+                header,
+                text: line.to_string(),
+            });
+        } else if let Some(ref mut section) = current_section {
+            section.text.push_str(line);
+        }
+    }
+
+    if let Some(section) = current_section {
+        sections.push(section);
+    }
+
+    sections
+}
+
+/// A topic file: whatever comes before the first capture header, kept verbatim, then the
+/// capture sections.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TopicFile {
+    pub preamble: String,
+    pub sections: Vec<CaptureSection>,
+}
+
+impl TopicFile {
+    pub fn parse(content: &str) -> Self {
+        Self {
+            preamble: preamble(content).to_string(),
+            sections: parse_sections(content),
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = self.preamble.clone();
+        for section in &self.sections {
+            text.push_str(section.to_text());
+        }
+        text
+    }
+
+    /// Splits the file around the capture `header`: everything before it, preamble included,
+    /// the capture itself and everything after it.
+    pub fn split(&self, header: &CaptureHeader) -> Option<(String, CaptureSection, String)> {
+        let (before, section, after) = split_sections(self.sections.clone(), header)?;
+        let mut before_text = self.preamble.clone();
+        for section in &before {
+            before_text.push_str(section.to_text());
+        }
+        let after_text = after.iter().map(CaptureSection::to_text).collect();
+        Some((before_text, section, after_text))
+    }
+
+    /// Replaces the preamble with edited text. It gets a final line break when captures follow
+    /// it, so the first header stays on a line of its own.
+    pub fn set_preamble(&mut self, preamble: &str) {
+        self.preamble = preamble.to_string();
+        if !self.preamble.is_empty() && !self.preamble.ends_with('\n') && !self.sections.is_empty()
+        {
+            self.preamble.push('\n');
+        }
+    }
+}
+
+/// Whether a line that is not a valid header was probably meant to be one, e.g. a header
+/// whose prefix, suffix or delimiters were damaged while editing the whole file.
+pub fn looks_like_header(line: &str) -> bool {
+    let line = line.trim_end_matches(['\n', '\r']);
+    let trimmed = line.trim();
+    if CaptureHeader::parse(line).is_some() {
+        return false;
+    }
+    trimmed.contains(HEADER_PREFIX)
+        || (trimmed.starts_with("<!--") && trimmed.to_lowercase().contains("yoink"))
+        || (trimmed.contains(HEADER_DELIMITER)
+            && (trimmed.starts_with("<!") || trimmed.ends_with(HEADER_SUFFIX)))
+}
+
+/// The 1-based numbers of the lines in `content` that look like broken capture headers.
+pub fn malformed_headers(content: &str) -> Vec<usize> {
+    content
+        .split_inclusive('\n')
+        .enumerate()
+        .filter(|(_, line)| looks_like_header(line))
+        .map(|(index, _)| index + 1)
+        .collect()
+}
+
+/// Rebuilds a broken header from its fields, if they survived: stray whitespace and a damaged
+/// prefix or suffix are dropped, the fields after the first delimiter are kept as they are.
+pub fn repair_header(line: &str) -> Option<CaptureHeader> {
+    let trimmed = line.trim();
+    let (_, fields) = trimmed.split_once(HEADER_DELIMITER)?;
+    let fields = fields.trim_end_matches(['-', '>']).trim_end();
+    CaptureHeader::parse(&format!(
+        "{}{}{}{}",
+        HEADER_PREFIX, HEADER_DELIMITER, fields, HEADER_SUFFIX
+    ))
+}
+
+/// Repairs every broken header of `content` that can be, keeping line endings. Returns the
+/// repaired content and the lines that still need fixing by hand.
+pub fn repair_headers(content: &str) -> (String, Vec<usize>) {
+    let mut repaired = String::with_capacity(content.len());
+    let mut unrepaired = Vec::new();
+
+    for (index, line) in content.split_inclusive('\n').enumerate() {
+        if !looks_like_header(line) {
+            repaired.push_str(line);
+            continue;
+        }
+        let text = line.trim_end_matches(['\n', '\r']);
+        match repair_header(text) {
+            Some(header) => {
+                repaired.push_str(&header.to_string());
+                repaired.push_str(&line[text.len()..]);
+            }
+            None => {
+                unrepaired.push(index + 1);
+                repaired.push_str(line);
+            }
+        }
+    }
+
+    (repaired, unrepaired)
+}
+
+/// Whatever comes before the first capture header of a topic file.
+pub fn preamble(content: &str) -> &str {
+    let mut offset = 0;
+    for line in content.split_inclusive('\n') {
+        if CaptureHeader::parse(line.trim_end_matches(['\n', '\r'])).is_some() {
+            break;
+        }
+        offset += line.len();
+    }
+    &content[..offset]
+}
+
+/// Rewrites the topic field of every header in a topic file, leaving the rest untouched.
+pub fn retopic(content: &str, topic: &str) -> String {
+    let mut retopiced = preamble(content).to_string();
+    for section in parse_sections(content) {
+        let mut header = section.header.clone();
+        header.topic = topic.to_string();
+        retopiced.push_str(section.with_header(header).to_text());
+    }
+    retopiced
+}
+
+/// A capture of a merged topic that clashed with one already in the target topic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Collision {
+    /// Both topics had a capture with this ID; the incoming one was given `new_id`.
+    Id {
+        subject: String,
+        id: String,
+        new_id: String,
+    },
+    /// Both topics had a capture with this subject. Both are kept.
+    Subject(String),
+}
+
+impl fmt::Display for Collision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Collision::Id {
+                subject,
+                id,
+                new_id,
+            } => write!(f, "{}: ID {} already taken, now {}", subject, id, new_id),
+            Collision::Subject(subject) => write!(f, "{}: subject used in both topics", subject),
+        }
+    }
+}
+
+/// Folds the captures of `incoming` into `target` under `topic`, ordered by timestamp. The
+/// target's preamble stays on top, followed by the incoming one. Captures whose ID is already
+/// taken get a new one. Legacy timestamps are ordered as if written in `zone`.
+pub fn merge_topics(
+    target: &str,
+    incoming: &str,
+    topic: &str,
+    zone: AssumedZone,
+) -> (String, Vec<Collision>) {
+    let mut sections = parse_sections(target);
+    let ids: HashSet<String> = sections
+        .iter()
+        .filter_map(|s| s.header.id.clone())
+        .collect();
+    let subjects: HashSet<String> = sections.iter().map(|s| s.header.subject.clone()).collect();
+    let mut collisions: Vec<Collision> = Vec::new();
+
+    for section in parse_sections(incoming) {
+        let mut header = section.header.clone();
+        header.topic = topic.to_string();
+        if let Some(id) = header.id.clone().filter(|id| ids.contains(id)) {
+            let new_id = CaptureHeader::new_id();
+            header.id = Some(new_id.clone());
+            collisions.push(Collision::Id {
+                subject: header.subject.clone(),
+                id,
+                new_id,
+            });
+        }
+        if subjects.contains(&header.subject) {
+            collisions.push(Collision::Subject(header.subject.clone()));
+        }
+        sections.push(section.with_header(header));
+    }
+    sections.sort_by_cached_key(|section| section.header.sort_key(zone));
+
+    let mut merged = String::new();
+    for text in [preamble(target), preamble(incoming)]
+        .into_iter()
+        .chain(sections.iter().map(CaptureSection::to_text))
+    {
+        if !merged.is_empty() && !merged.ends_with('\n') {
+            merged.push('\n');
+        }
+        merged.push_str(text);
+    }
+    (merged, collisions)
+}
+
+/// Splits sections around the one that is the same capture as `header`.
+pub fn split_sections(
+    sections: Vec<CaptureSection>,
+    header: &CaptureHeader,
+) -> Option<(Vec<CaptureSection>, CaptureSection, Vec<CaptureSection>)> {
+    let mut before: Vec<CaptureSection> = Vec::new();
+    let mut content: Option<CaptureSection> = None;
+    let mut after: Vec<CaptureSection> = Vec::new();
+
+    for section in sections {
+        if content.is_none() && section.header.is_same_capture(header) {
+            content = Some(section);
+        } else if content.is_none() {
+            before.push(section);
+        } else {
+            after.push(section);
+        }
+    }
+
+    content.map(|content| (before, content, after))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOPIC: &str =
+        "<!--yoink::::2025-04-01 09:00:00::::dev::::snippets::::id=01JQDEV00000000000000000001-->
+```rust
+fn main() {
+    println!(\"indented\");
+}
+```
+\t- tab-indented item
+\t\t- nested item
+    trailing spaces  \x20
+
+<!--yoink::::2025-04-02 09:00:00::::dev::::crlf-->\r
+\tkeeps\r
+    its endings\r
+<!--yoink::::2025-04-03 09:00:00::::dev::::last::::id=01JQDEV00000000000000000003-->
+  no final newline";
+
+    #[test]
+    fn header_round_trips_exactly() {
+        for line in TOPIC.lines() {
+            let line = line.trim_end_matches('\r');
+            if let Some(header) = CaptureHeader::parse(line) {
+                assert_eq!(header.to_string(), line);
+            }
+        }
+    }
+
+    #[test]
+    fn sections_round_trip_byte_for_byte() {
+        let sections = parse_sections(TOPIC);
+        assert_eq!(sections.len(), 3);
+
+        let rebuilt: String = sections.iter().map(CaptureSection::to_text).collect();
+        assert_eq!(rebuilt, TOPIC);
+    }
+
+    #[test]
+    fn editing_a_body_keeps_the_separator_before_the_next_header() {
+        for section in parse_sections(TOPIC) {
+            // The editor shows the body without its trailing line breaks and adds one back.
+            let edited = format!("{}\n", section.body().trim_end_matches(['\n', '\r']));
+            assert_eq!(section.with_body(section.header.clone(), &edited), section);
+        }
+
+        let sections = parse_sections(TOPIC);
+        let edited = sections[0].with_body(sections[0].header.clone(), "changed\n");
+        assert_eq!(edited.body(), "changed\n\n");
+        let rebuilt = format!("{}{}{}", edited.text, sections[1].text, sections[2].text);
+        assert_eq!(parse_sections(&rebuilt)[1..], sections[1..]);
+
+        let header_only = &parse_sections("<!--yoink::::2025-04-01 09:00:00::::dev::::empty-->")[0];
+        assert_eq!(
+            header_only
+                .with_body(header_only.header.clone(), "new")
+                .text,
+            "<!--yoink::::2025-04-01 09:00:00::::dev::::empty-->\nnew\n"
+        );
+    }
+
+    #[test]
+    fn removing_a_section_leaves_the_others_untouched() {
+        let sections = parse_sections(TOPIC);
+        let (before, content, after) =
+            split_sections(sections.clone(), &sections[1].header).unwrap();
+        assert_eq!(content.body(), "\tkeeps\r\n    its endings\r\n");
+
+        let rebuilt: String = before
+            .iter()
+            .chain(after.iter())
+            .map(CaptureSection::to_text)
+            .collect();
+        assert_eq!(rebuilt, format!("{}{}", sections[0].text, sections[2].text));
+        assert!(rebuilt.contains("    println!(\"indented\");\n"));
+        assert!(rebuilt.contains("\t\t- nested item\n"));
+        assert!(rebuilt.contains("    trailing spaces   \n"));
+    }
+
+    const TAGGED: &str = "preamble #ignored
+<!--yoink::::2025-04-01 09:00:00::::dev::::tagged::::id=01JQDEV00000000000000000004::::tags=rust,ideas-->\r
+Try #Rust-lang and #ideas/later, not # headings, #42 or a#b.\r
+`#inline` code
+```
+#[derive(Debug)] #fenced
+```
+#tail
+";
+
+    #[test]
+    fn retopic_rewrites_every_header_and_nothing_else() {
+        let retopiced = retopic(TAGGED, "rust");
+        assert!(retopiced.starts_with("preamble #ignored\n<!--yoink::::2025-04-01 09:00:00::::rust::::tagged::::id=01JQDEV00000000000000000004::::tags=rust,ideas-->\r\n"));
+        assert_eq!(retopic(&retopiced, "dev"), TAGGED);
+        assert_eq!(preamble(TOPIC), "");
+    }
+
+    #[test]
+    fn merge_orders_by_timestamp_and_reports_collisions() {
+        let incoming = "notes on old
+<!--yoink::::2025-04-02 12:00:00::::old::::snippets::::id=01JQDEV00000000000000000001-->
+moved
+<!--yoink::::2025-03-01 09:00:00::::old::::first-->
+earliest";
+        let (merged, collisions) = merge_topics(TOPIC, incoming, "dev", AssumedZone::Local);
+        let sections = parse_sections(&merged);
+        let subjects: Vec<&str> = sections.iter().map(|s| s.header.subject.as_str()).collect();
+        assert_eq!(subjects, ["first", "snippets", "crlf", "snippets", "last"]);
+        assert!(sections.iter().all(|s| s.header.topic == "dev"));
+        assert!(merged.starts_with(
+            "notes on old\n<!--yoink::::2025-03-01 09:00:00::::dev::::first-->\nearliest\n"
+        ));
+        assert!(merged.contains(&sections[2].text));
+        assert_eq!(sections[2].text, parse_sections(TOPIC)[1].text);
+
+        assert_eq!(collisions.len(), 2);
+        match &collisions[0] {
+            Collision::Id { id, new_id, .. } => {
+                assert_eq!(id, "01JQDEV00000000000000000001");
+                assert_eq!(sections[3].header.id.as_ref(), Some(new_id));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(collisions[1], Collision::Subject("snippets".to_string()));
+    }
+
+    #[test]
+    fn header_tags_round_trip_and_normalize() {
+        let line = TAGGED.lines().nth(1).unwrap().trim_end_matches('\r');
+        let header = CaptureHeader::parse(line).unwrap();
+        assert_eq!(header.tags, vec!["rust", "ideas"]);
+        assert_eq!(header.to_string(), line);
+
+        let header = CaptureHeader::parse(
+            "<!--yoink::::2025-04-01 09:00:00::::dev::::s::::tags=#Rust, rust,,bad tag!-->",
+        )
+        .unwrap();
+        assert_eq!(header.tags, vec!["rust", "bad"]);
+        assert!(
+            CaptureHeader::parse("<!--yoink::::t::::dev::::s::::tags=a::::tags=b-->").is_none()
+        );
+    }
+
+    #[test]
+    fn sections_collect_header_and_inline_tags() {
+        let sections = parse_sections(TAGGED);
+        assert_eq!(
+            sections[0].tags(),
+            vec!["ideas", "ideas/later", "rust", "rust-lang", "tail"]
+        );
+    }
+
+    #[test]
+    fn renaming_a_tag_merges_and_keeps_the_rest() {
+        let renamed = rename_tag(TAGGED, "rust", "ideas");
+        assert!(renamed.contains("::::tags=ideas-->\r\n"));
+        assert_eq!(
+            rename_tag(&renamed, "rust-lang", "lang")
+                .lines()
+                .nth(2)
+                .unwrap(),
+            "Try #lang and #ideas/later, not # headings, #42 or a#b."
+        );
+        assert!(renamed.starts_with("preamble #ignored\n"));
+
+        let renamed = rename_tag(TAGGED, "fenced", "x");
+        assert_eq!(renamed, TAGGED);
+        let renamed = rename_tag(TAGGED, "tail", "end");
+        assert!(renamed.ends_with("```\n#end\n"));
+    }
+
+    #[test]
+    fn finds_and_repairs_broken_headers() {
+        let content = "<!--yoink::::2025-04-01 09:00:00::::dev::::ok-->
+<!-- a plain comment -->
+  <!--yoink::::2025-04-02 09:00:00::::dev::::indented-->\r
+<!--yonk::::2025-04-03 09:00:00::::dev::::typo::::id=01JQDEV00000000000000000003->
+<!--yoink::::2025-04-04 09:00:00::::dev-->
+body
+";
+        assert_eq!(malformed_headers(content), [3, 4, 5]);
+
+        let (repaired, unrepaired) = repair_headers(content);
+        assert_eq!(unrepaired, [5]);
+        assert_eq!(
+            repaired.lines().nth(2),
+            Some("<!--yoink::::2025-04-02 09:00:00::::dev::::indented-->")
+        );
+        assert_eq!(
+            repaired.lines().nth(3),
+            Some("<!--yoink::::2025-04-03 09:00:00::::dev::::typo::::id=01JQDEV00000000000000000003-->")
+        );
+        assert!(repaired.contains("indented-->\r\n"));
+        assert_eq!(parse_sections(&repaired).len(), 3);
+        assert_eq!(malformed_headers(&repaired), [5]);
+    }
+
+    #[test]
+    fn adversarial_fields_survive_a_round_trip() {
+        let subjects = [
+            "a::::b",
+            "::::",
+            "::::::::id=01JQDEV00000000000000000009",
+            "ends with:",
+            ":starts with",
+            "note: keep single colons",
+            "closes --> early",
+            "-->",
+            "ends with a dash-",
+            "multi\nline\r\nsubject",
+            "50% done, literally %3A and %25",
+            "tags=a,b",
+            "<!--yoink::::nested-->",
+        ];
+
+        for subject in subjects {
+            let mut header = CaptureHeader::new("dev::::ops-->", subject);
+            header.tags = vec!["rust".to_string()];
+            let line = header.to_string();
+
+            assert!(!line.contains(['\n', '\r']), "{:?}", line);
+            assert_eq!(line.matches("-->").count(), 1, "{:?}", line);
+            assert_eq!(line.matches(HEADER_DELIMITER).count(), 5, "{:?}", line);
+            assert_eq!(CaptureHeader::parse(&line), Some(header.clone()));
+
+            let file = format!(
+                "{}\nbody\n{}\nsecond\n",
+                line,
+                CaptureHeader::new("dev", "next")
+            );
+            let sections = parse_sections(&file);
+            assert_eq!(sections.len(), 2);
+            assert_eq!(sections[0].header, header);
+            assert_eq!(sections[0].body(), "body\n");
+        }
+    }
+
+    #[test]
+    fn unescaped_legacy_fields_read_back_unchanged() {
+        let line = "<!--yoink::::2025-04-01 09:00:00::::dev::::re: 100% %zz done-->";
+        let header = CaptureHeader::parse(line).unwrap();
+        assert_eq!(header.subject, "re: 100% %zz done");
+        assert_eq!(escape_field("re: plain"), "re: plain");
+    }
+
+    #[test]
+    fn topic_file_keeps_its_preamble_verbatim() {
+        let content = format!("# Dev notes\r\n---\nowner: me\n\n{}", TOPIC);
+        let file = TopicFile::parse(&content);
+        assert_eq!(file.preamble, "# Dev notes\r\n---\nowner: me\n\n");
+        assert_eq!(file.to_text(), content);
+
+        let (before, section, after) = file.split(&file.sections[1].header).unwrap();
+        assert!(before.starts_with("# Dev notes\r\n"));
+        assert_eq!(format!("{}{}{}", before, section.to_text(), after), content);
+
+        let mut file = file;
+        file.set_preamble("# Renamed");
+        assert!(file.to_text().starts_with("# Renamed\n<!--yoink::::"));
+        assert_eq!(file.sections.len(), 3);
+
+        assert_eq!(TopicFile::parse("only notes").preamble, "only notes");
+        assert!(TopicFile::parse("only notes").sections.is_empty());
+    }
+
+    #[test]
+    fn timestamps_carry_their_offset() {
+        let now = CaptureHeader::timestamp_now();
+        assert!(DateTime::parse_from_rfc3339(&now).is_ok(), "{}", now);
+
+        let plus_two = AssumedZone::parse("+02:00").unwrap();
+        let mut legacy = CaptureHeader::new("dev", "legacy");
+        legacy.timestamp = "2025-04-01 09:00:00".to_string();
+        assert_eq!(
+            legacy.time(plus_two).unwrap().to_rfc3339(),
+            "2025-04-01T09:00:00+02:00"
+        );
+        assert!(legacy.migrate_timestamp(plus_two));
+        assert_eq!(legacy.timestamp, "2025-04-01T09:00:00+02:00");
+        assert!(!legacy.migrate_timestamp(plus_two));
+        assert_eq!(
+            CaptureHeader::parse(&legacy.to_string()),
+            Some(legacy.clone())
+        );
+
+        // 08:30 in New York is after 09:00 in Berlin.
+        let mut travelling = CaptureHeader::new("dev", "travelling");
+        travelling.timestamp = "2025-04-01T08:30:00-04:00".to_string();
+        let mut home = CaptureHeader::new("dev", "home");
+        home.timestamp = "2025-04-01T09:00:00+02:00".to_string();
+        let local = AssumedZone::Local;
+        assert!(home.sort_key(local) < travelling.sort_key(local));
+
+        // 08:00 written without an offset in New York comes after 10:00 UTC, not before.
+        let new_york = AssumedZone::parse("-04:00").unwrap();
+        let mut legacy = CaptureHeader::new("dev", "legacy");
+        legacy.timestamp = "2025-04-01 08:00:00".to_string();
+        let mut utc = CaptureHeader::new("dev", "utc");
+        utc.timestamp = "2025-04-01T10:00:00+00:00".to_string();
+        assert!(utc.sort_key(new_york) < legacy.sort_key(new_york));
+        assert!(legacy.sort_key(plus_two) < utc.sort_key(plus_two));
+
+        assert_eq!(
+            AssumedZone::parse("UTC"),
+            FixedOffset::east_opt(0).map(AssumedZone::Fixed)
+        );
+        assert_eq!(AssumedZone::parse("local"), Some(AssumedZone::Local));
+        assert_eq!(AssumedZone::parse("Mars/Olympus"), None);
+    }
+}
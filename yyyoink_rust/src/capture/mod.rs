@@ -1,3 +1,5 @@
 pub mod capture_models;
 pub mod capture_pane;
+pub mod capture_record;
 pub mod capture_sidebar;
+pub mod topic_name;
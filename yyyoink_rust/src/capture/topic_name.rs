@@ -0,0 +1,119 @@
+use crate::enums::error::Error;
+use std::fmt;
+
+/// Longest topic accepted, in characters. Keeps `_{topic}.md` well inside file name limits.
+const MAX_TOPIC_CHARS: usize = 100;
+/// Characters no topic may contain: path separators and what Windows refuses in file names.
+const FORBIDDEN_CHARS: [char; 9] = ['/', '\\', '<', '>', ':', '"', '|', '?', '*'];
+
+/// A topic that is safe to turn into a file name of the vault, `_{topic}.md`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopicName(String);
+
+impl TopicName {
+    /// Checks a typed topic, ignoring surrounding whitespace.
+    pub fn parse(raw: &str) -> Result<Self, Error> {
+        let topic = raw.trim();
+        let invalid = |reason: String| {
+            eprintln!("Rejected topic {:?}: {}", raw, reason);
+            Err(Error::InvalidTopic(reason))
+        };
+
+        if topic.is_empty() {
+            return invalid("a topic cannot be empty".to_string());
+        }
+        if topic.chars().count() > MAX_TOPIC_CHARS {
+            return invalid(format!("longer than {} characters", MAX_TOPIC_CHARS));
+        }
+        if let Some(c) = topic
+            .chars()
+            .find(|c| c.is_control() || FORBIDDEN_CHARS.contains(c))
+        {
+            return invalid(format!("{:?} is not allowed", c));
+        }
+        if topic.starts_with('.') || topic.ends_with('.') {
+            return invalid("cannot start or end with a dot".to_string());
+        }
+
+        Ok(Self(topic.to_string()))
+    }
+
+    /// Turns any input into a valid topic by replacing what is not allowed with `-`, or `None`
+    /// if nothing usable is left.
+    pub fn slugify(raw: &str) -> Option<Self> {
+        let mut slug = String::new();
+        for c in raw.trim().chars() {
+            let c = if c.is_control() || FORBIDDEN_CHARS.contains(&c) {
+                '-'
+            } else {
+                c
+            };
+            if !(c == '-' && slug.ends_with('-')) {
+                slug.push(c);
+            }
+        }
+
+        let slug: String = slug
+            .trim_matches(|c: char| c == '.' || c == '-' || c.is_whitespace())
+            .chars()
+            .take(MAX_TOPIC_CHARS)
+            .collect();
+        Self::parse(&slug).ok()
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The topic file, e.g. `_work.md`.
+    pub fn file_name(&self) -> String {
+        format!("_{}.md", self.0)
+    }
+}
+
+impl fmt::Display for TopicName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_unsafe_topics() {
+        for topic in [
+            "",
+            "   ",
+            "../../.bashrc",
+            "..",
+            "a/b",
+            "a\\b",
+            "/etc/passwd",
+            "C:evil",
+            "new\nline",
+            "nul\0byte",
+            "trailing.",
+            ".hidden",
+            &"x".repeat(MAX_TOPIC_CHARS + 1),
+        ] {
+            assert!(TopicName::parse(topic).is_err(), "{:?}", topic);
+        }
+
+        let topic = TopicName::parse("  rust notes-2025 ").unwrap();
+        assert_eq!(topic.as_str(), "rust notes-2025");
+        assert_eq!(topic.file_name(), "_rust notes-2025.md");
+        assert!(TopicName::parse("café.log").is_ok());
+    }
+
+    #[test]
+    fn slugifies_into_a_safe_topic() {
+        let slug = |raw: &str| TopicName::slugify(raw).map(|topic| topic.to_string());
+        assert_eq!(slug("../../.bashrc"), Some("bashrc".to_string()));
+        assert_eq!(slug("work/2025:q1"), Some("work-2025-q1".to_string()));
+        assert_eq!(slug("a//\\\\b"), Some("a-b".to_string()));
+        assert_eq!(slug("../"), None);
+        assert_eq!(slug("dev"), Some("dev".to_string()));
+    }
+}
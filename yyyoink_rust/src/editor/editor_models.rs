@@ -1,9 +1,46 @@
+use crate::capture::capture_record;
+use crate::utilities::file::LineEndings;
+use crate::utilities::markdown::Preview;
 use iced::widget::text_editor;
+use std::fmt;
+
+/// Where the rendered markdown preview is shown.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PreviewMode {
+    #[default]
+    Off,
+    SideBySide,
+    Only,
+}
+
+impl PreviewMode {
+    pub const ALL: [PreviewMode; 3] =
+        [PreviewMode::Off, PreviewMode::SideBySide, PreviewMode::Only];
+}
+
+impl fmt::Display for PreviewMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PreviewMode::Off => write!(f, "No preview"),
+            PreviewMode::SideBySide => write!(f, "Side by side"),
+            PreviewMode::Only => write!(f, "Preview only"),
+        }
+    }
+}
 
 pub struct Editor {
     pub editor_content: text_editor::Content,
     pub is_saved: bool,
     pub new_file: String,
+    pub search: String,
+    /// Set when the file behind the editor changed on disk while it had unsaved edits.
+    pub conflict: Option<String>,
+    /// 1-based lines holding broken capture headers; the file is not saved while any remain.
+    pub broken_headers: Vec<usize>,
+    /// The line endings of the file open in whole-file mode.
+    pub line_endings: LineEndings,
+    pub preview_mode: PreviewMode,
+    pub preview: Preview,
 }
 
 impl Editor {
@@ -12,6 +49,39 @@ impl Editor {
             editor_content: text_editor::Content::new(),
             is_saved: true,
             new_file: String::new(),
+            search: String::new(),
+            conflict: None,
+            broken_headers: Vec::new(),
+            line_endings: LineEndings::default(),
+            preview_mode: PreviewMode::default(),
+            preview: Preview::default(),
+        }
+    }
+
+    /// Loads a whole file into the editor, remembering its line endings for the save.
+    pub fn open_file_text(&mut self, content: &str) {
+        self.line_endings = LineEndings::detect(content);
+        self.editor_content = text_editor::Content::with_text(&content.replace("\r\n", "\n"));
+        self.is_saved = true;
+        self.conflict = None;
+        self.refresh_preview();
+        self.check_headers();
+    }
+
+    /// The editor text the way it is written back to the open file.
+    pub fn file_text(&self) -> String {
+        self.line_endings.restore(&self.editor_content.text())
+    }
+
+    /// Rechecks the editor text for broken capture headers.
+    pub fn check_headers(&mut self) {
+        self.broken_headers = capture_record::malformed_headers(&self.editor_content.text());
+    }
+
+    /// Re-renders the parts of the preview that changed, if it is shown.
+    pub fn refresh_preview(&mut self) {
+        if self.preview_mode != PreviewMode::Off {
+            self.preview.update(&self.editor_content.text());
         }
     }
 }
@@ -4,6 +4,12 @@ use std::{fmt, io::ErrorKind};
 pub enum Error {
     PermissionDenied,
     FileNotFound,
+    CaptureNotFound,
+    FileChanged,
+    FileExists,
+    SameFile,
+    InvalidTopic(String),
+    OutsideVault,
     IoError(ErrorKind),
 }
 
@@ -12,6 +18,12 @@ impl fmt::Display for Error {
         match self {
             Error::PermissionDenied => write!(f, "Permission Denied"),
             Error::FileNotFound => write!(f, "File Not Found"),
+            Error::CaptureNotFound => write!(f, "Capture Not Found"),
+            Error::FileChanged => write!(f, "File Changed Since"),
+            Error::FileExists => write!(f, "File Already Exists"),
+            Error::SameFile => write!(f, "Same File"),
+            Error::InvalidTopic(reason) => write!(f, "Invalid Topic: {}", reason),
+            Error::OutsideVault => write!(f, "Path Outside Vault"),
             Error::IoError(kind) => write!(f, "IO Error: {:?}", kind),
         }
     }
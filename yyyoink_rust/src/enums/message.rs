@@ -1,28 +1,73 @@
 use super::error;
+use crate::capture::capture_record::{CaptureRecord, CaptureSection, Collision};
+use crate::editor::editor_models::PreviewMode;
+use crate::utilities::history::HistoryEntry;
+use crate::utilities::index::IndexHit;
+use crate::utilities::settings::Vault;
+use crate::utilities::trash::TrashItem;
 use iced::event::Event;
 use iced::widget::{pane_grid, text_editor};
 use std::path::PathBuf;
 
 #[derive(Debug, Clone)]
 pub enum Message {
-    CapturesLoaded(Result<Vec<Vec<String>>, error::Error>),
-    FilesLoaded(Result<Vec<String>, error::Error>),
-    CapturesReloaded(Result<Vec<Vec<String>>, error::Error>),
+    CapturesLoaded(String, Result<Vec<CaptureRecord>, error::Error>),
+    FilesLoaded(String, Result<Vec<String>, error::Error>),
+    CapturesReloaded(String, Result<Vec<CaptureRecord>, error::Error>),
+    SwitchVault(Vault),
+    TopicsChanged(String, Vec<String>),
+    TopicsReindexed(String, Result<Vec<(String, Option<String>)>, error::Error>),
+    ViewTrash,
+    HideTrash,
+    TrashLoaded(String, Result<Vec<TrashItem>, error::Error>),
+    RestoreTrash(usize),
+    TrashRestored(Result<bool, error::Error>),
+    ViewTags,
+    HideTags,
+    TagRenameSelected(String),
+    TagRenameInput(String),
+    RenameTag,
+    TagRenamed(Result<usize, error::Error>),
+    TagFilterChanged(Option<String>),
+    ViewTopics,
+    HideTopics,
+    OpenExistingTopic,
+    PreambleSelected(String),
+    PreambleOpened(String, Result<String, error::Error>),
+    UpdatePreamble,
+    HideExistingTopic,
+    TopicEditSelected(String),
+    TopicEditInput(String),
+    RenameTopic,
+    MergeTopic,
+    TopicRenamed(Result<String, error::Error>),
+    TopicMerged(Result<Vec<Collision>, error::Error>),
+    Journaled(Option<HistoryEntry>),
+    Undo,
+    Redo,
+    Undone(HistoryEntry, Result<bool, error::Error>),
+    Redone(HistoryEntry, Result<bool, error::Error>),
     Ignore,
     NewFileInput(String),
     CaptureSearchChanged(String),
+    BodySearched(String, String, Result<Vec<IndexHit>, error::Error>),
+    BodyHitSelected(usize),
     FileSearchChanged(String),
     CaptureTopicChanged(String),
     CaptureSubjectChanged(String),
+    CaptureTagsChanged(String),
+    CaptureTagSuggested(String),
     CaptureFormContentChanged(text_editor::Action),
     CaptureSelected(usize),
-    CaptureOpened(Result<(String, PathBuf, String), error::Error>),
+    CaptureOpened(Result<CaptureRecord, error::Error>),
     FileSelected(String),
     FileWritten(Result<PathBuf, error::Error>),
-    EditorFileOpened(Result<Vec<String>, error::Error>),
+    EditorFileOpened(String, Result<String, error::Error>),
+    FileSaved(Result<PathBuf, error::Error>),
     UpdateCapture,
     SubselectCapture,
     UpdateFile,
+    RepairHeaders,
     CreateFile,
     ViewModalHelper,
     CreateNewFile,
@@ -38,18 +83,14 @@ pub enum Message {
     HideModalHelper,
     DeleteCapture(usize),
     CaptureDeleted(Result<bool, error::Error>),
+    MoveTopicInput(String),
+    MoveCapture,
+    CaptureMoved(Result<CaptureRecord, error::Error>),
     Event(Event),
     PaneResized(pane_grid::ResizeEvent),
     Edit,
     EditorContentChanged(text_editor::Action),
-    SetInitialEditorText(
-        Result<
-            (
-                Vec<(String, Vec<String>)>,
-                (String, Vec<String>),
-                Vec<(String, Vec<String>)>,
-            ),
-            error::Error,
-        >,
-    ),
+    PreviewModeChanged(PreviewMode),
+    LinkClicked(String),
+    SetInitialEditorText(Result<(String, CaptureSection, String), error::Error>),
 }
@@ -3,4 +3,5 @@ pub enum PaneState {
     CaptureFormPane,
     EditorSidebarPane,
     EditorPane,
+    PreviewPane,
 }
@@ -5,31 +5,87 @@ mod utilities;
 mod yoink;
 use capture::capture_models::Capture;
 use capture::capture_pane::CapturePane;
+use capture::capture_record::{self, CaptureHeader, TopicFile};
 use capture::capture_sidebar::CaptureSidebar;
-use chrono::prelude::*;
-pub use editor::editor_models::Editor;
+use capture::topic_name::TopicName;
+pub use editor::editor_models::{Editor, PreviewMode};
 use editor::editor_pane::EditorPane;
 use editor::editor_sidebar::EditorSidebar;
+use enums::error::Error;
 use enums::message::Message;
 use enums::pane::PaneState;
 use iced::event::{self, Event};
 use iced::keyboard;
 use iced::keyboard::key;
 use iced::widget::text_editor::Content;
-use iced::widget::{self, button, column as col, container, pane_grid, text, text_input};
+use iced::widget::{self, button, column as col, container, pane_grid, row, text, text_input};
 use iced::{Color, Element, Font, Length, Subscription, Task};
 // use iced_aw::ContextMenu;
 use std::time::Instant;
 use utilities::file;
+use utilities::history::{self, History};
+use utilities::search::Query;
+use utilities::settings;
+use utilities::tool;
+use utilities::trash;
+use utilities::watcher;
 use yoink::yoink_models::Yoink;
 
 pub fn main() -> iced::Result {
+    settings::init();
+    if settings::rebuild_cache_requested() {
+        rebuild_cache();
+        return Ok(());
+    }
+    if settings::migrate_timestamps_requested() {
+        migrate_timestamps();
+        return Ok(());
+    }
     iced::application("Yoink Desktop", Yoink::update, Yoink::view)
         .subscription(Yoink::subscription)
         .default_font(Font::MONOSPACE)
         .run_with(Yoink::new)
 }
 
+/// `--rebuild-cache`: rebuilds the capture cache of every vault, then exits.
+#[cfg(feature = "sqlite-cache")]
+fn rebuild_cache() {
+    for vault in &settings::get().vaults {
+        match utilities::cache::rebuild(&vault.path) {
+            Ok(count) => println!("Rebuilt cache for vault {}: {} captures.", vault, count),
+            Err(e) => eprintln!("Failed to rebuild cache for vault {}: {}", vault, e),
+        }
+    }
+}
+
+#[cfg(not(feature = "sqlite-cache"))]
+fn rebuild_cache() {
+    eprintln!("Built without the sqlite-cache feature, there is no cache to rebuild.");
+}
+
+/// `--migrate-timestamps`: rewrites timestamps without an offset in every vault as RFC 3339,
+/// reading them in the configured `legacy_timezone`, then exits.
+fn migrate_timestamps() {
+    let runtime = match tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Failed to start the migration.\nUnderlying error: {}", e);
+            return;
+        }
+    };
+    let settings = settings::get();
+    for vault in &settings.vaults {
+        let migration = file::migrate_timestamps(vault.path.clone(), settings.legacy_timezone);
+        match runtime.block_on(migration) {
+            Ok(count) => println!("Migrated vault {}: {} timestamps.", vault, count),
+            Err(e) => eprintln!("Failed to migrate vault {}: {}", vault, e),
+        }
+    }
+}
+
 impl Yoink {
     pub fn new() -> (Self, Task<Message>) {
         let (mut panes, sidebar) = pane_grid::State::new(PaneState::CaptureSidebarPane);
@@ -38,36 +94,50 @@ impl Yoink {
             sidebar,
             PaneState::CaptureFormPane,
         );
-        (
-            Self {
-                is_capture: true,
-                editor: Editor::new(),
-                captures: Vec::new(),
-                capture: Capture::new(),
-                capture_pane: CapturePane::new(),
-                capture_sidebar: CaptureSidebar::new(),
-                files: Vec::new(),
-                editor_pane: EditorPane::new(),
-                editor_sidebar: EditorSidebar::new(),
-                opened_file: Vec::new(),
-                ui_error: String::new(),
-                show_helper: false,
-                panes,
-                last_updated: Instant::now(),
-                submit_enabled: false,
-                is_subselect_capture: false,
-                newfile_submit_enabled: false,
-                modal_helper: false,
-            },
-            Task::batch([
-                Task::perform(file::load_captures(), Message::CapturesLoaded),
-                Task::perform(file::load_files(), Message::FilesLoaded),
-            ]),
-        )
+        let settings = settings::get();
+        let yoink = Self {
+            is_capture: true,
+            editor: Editor::new(),
+            captures: Vec::new(),
+            capture: Capture::new(),
+            capture_pane: CapturePane::new(),
+            capture_sidebar: CaptureSidebar::new(),
+            files: Vec::new(),
+            editor_pane: EditorPane::new(),
+            editor_sidebar: EditorSidebar::new(),
+            opened_file: None,
+            opened_preamble: None,
+            ui_error: String::new(),
+            show_helper: false,
+            panes,
+            last_updated: Instant::now(),
+            submit_enabled: false,
+            is_subselect_capture: false,
+            newfile_submit_enabled: false,
+            modal_helper: false,
+            show_trash: false,
+            trash: Vec::new(),
+            show_tags: false,
+            show_topics: false,
+            topic_edit_from: None,
+            topic_edit_to: String::new(),
+            topic_collisions: Vec::new(),
+            existing_topic: None,
+            tag_rename_from: None,
+            tag_rename_to: String::new(),
+            history: History::new(),
+            vaults: settings.vaults.clone(),
+            active_vault: settings.default_vault().clone(),
+        };
+        let task = yoink.load_vault();
+        (yoink, task)
     }
 
     fn subscription(&self) -> Subscription<Message> {
-        event::listen().map(Message::Event)
+        Subscription::batch([
+            event::listen().map(Message::Event),
+            watcher::watch(self.active_vault.clone()),
+        ])
     }
 
     fn update(&mut self, message: Message) -> Task<Message> {
@@ -77,8 +147,16 @@ impl Yoink {
                     self.is_capture = false;
 
                     let (mut panes, sidebar) = pane_grid::State::new(PaneState::EditorSidebarPane);
-                    let _pane =
-                        panes.split(pane_grid::Axis::Vertical, sidebar, PaneState::EditorPane);
+                    let (editor_pane, _) = panes
+                        .split(pane_grid::Axis::Vertical, sidebar, PaneState::EditorPane)
+                        .expect("Failed to split editor panes.");
+                    if self.editor.preview_mode == PreviewMode::SideBySide {
+                        panes.split(
+                            pane_grid::Axis::Vertical,
+                            editor_pane,
+                            PaneState::PreviewPane,
+                        );
+                    }
                     self.panes = panes;
                 } else {
                     self.is_capture = true;
@@ -99,44 +177,79 @@ impl Yoink {
                     println!("Written to {}", path.display());
                     self.capture.before = "".to_string();
                     self.capture.after = "".to_string();
+                    self.capture.opened_section = None;
                     self.editor.editor_content = Content::with_text("");
+                    self.editor.refresh_preview();
+                    self.editor.conflict = None;
                 }
 
-                Task::perform(file::load_captures(), Message::CapturesReloaded)
+                self.load_captures(Message::CapturesReloaded)
             }
             Message::SetInitialEditorText(result) => {
-                let mut content_input = String::new();
                 if let Ok((before, content, after)) = result {
-                    self.capture.before = "".to_string();
-                    self.capture.after = "".to_string();
-                    self.editor.editor_content = Content::with_text("");
-                    for (header, lines) in before {
-                        self.capture
-                            .before
-                            .push_str(&format!("{}\n", header.trim()));
-                        for line in lines {
-                            self.capture.before.push_str(&format!("{}\n", line.trim()));
-                        }
-                    }
-                    for (header, lines) in after {
-                        self.capture.after.push_str(&format!("{}\n", header.trim()));
-                        for line in lines {
-                            self.capture.after.push_str(&format!("{}\n", line.trim()));
-                        }
-                    }
-                    for line in content.1 {
-                        content_input.push_str(&format!("{}\n", line.trim()));
-                    }
-                    self.editor.editor_content = Content::with_text(&content_input.trim());
+                    self.capture.before = before;
+                    self.capture.after = after;
+                    let body = content.body().trim_end_matches(['\n', '\r']);
+                    self.editor.editor_content = Content::with_text(body);
+                    self.editor.refresh_preview();
+                    self.editor.is_saved = true;
+                    self.editor.conflict = None;
+                    self.capture.opened_section = Some(content);
                 }
                 Task::none()
             }
             Message::EditorContentChanged(action) => {
+                let is_edit = action.is_edit();
+                if is_edit {
+                    self.editor.is_saved = false;
+                }
                 self.editor.editor_content.perform(action);
-                self.editor.is_saved = false;
+                if is_edit {
+                    self.editor.refresh_preview();
+                    if !self.editor.broken_headers.is_empty() {
+                        self.editor.check_headers();
+                    }
+                }
 
                 Task::none()
             }
+            Message::PreviewModeChanged(mode) => {
+                self.editor.preview_mode = mode;
+                if mode == PreviewMode::Off {
+                    self.editor.preview.clear();
+                }
+                self.editor.refresh_preview();
+
+                let preview_pane = self
+                    .panes
+                    .iter()
+                    .find(|(_, state)| matches!(state, PaneState::PreviewPane))
+                    .map(|(pane, _)| *pane);
+                let editor_pane = self
+                    .panes
+                    .iter()
+                    .find(|(_, state)| matches!(state, PaneState::EditorPane))
+                    .map(|(pane, _)| *pane);
+                match (mode, preview_pane, editor_pane) {
+                    (PreviewMode::SideBySide, None, Some(editor_pane)) => {
+                        self.panes.split(
+                            pane_grid::Axis::Vertical,
+                            editor_pane,
+                            PaneState::PreviewPane,
+                        );
+                    }
+                    (PreviewMode::Off | PreviewMode::Only, Some(preview_pane), _) => {
+                        self.panes.close(preview_pane);
+                    }
+                    _ => {}
+                }
+                Task::none()
+            }
+            Message::LinkClicked(url) => {
+                println!("Opening {}", url);
+                tool::open_link(&url);
+                Task::none()
+            }
             Message::CaptureSelected(index) => {
                 if let Some(capture_data) = self.captures.get(index) {
                     let capture_input = capture_data.clone();
@@ -148,7 +261,10 @@ impl Yoink {
             Message::FileSelected(filename) => {
                 if let Some(file) = self.files.iter().find(|f| f == &&filename) {
                     let file_input = file.clone();
-                    Task::perform(file::file_opened(file_input), Message::EditorFileOpened)
+                    Task::perform(
+                        file::file_opened(self.vault_root(), file_input),
+                        move |result| Message::EditorFileOpened(filename.clone(), result),
+                    )
                 } else {
                     Task::none()
                 }
@@ -191,7 +307,17 @@ impl Yoink {
                 if let Some(capture_data) = self.captures.get(index) {
                     println!("Deleting line: {}", index);
                     let capture_input = capture_data.clone();
-                    Task::perform(file::delete_capture(capture_input), Message::CaptureDeleted)
+                    let root = self.vault_root();
+                    let file = capture_input.header.file_name();
+                    self.perform_recorded(
+                        history::record_trashed(
+                            root.clone(),
+                            format!("delete capture {}", capture_input.header.subject),
+                            vec![file],
+                            file::delete_capture(root, capture_input),
+                        ),
+                        Message::CaptureDeleted,
+                    )
                 } else {
                     Task::none()
                 }
@@ -200,25 +326,82 @@ impl Yoink {
             Message::CaptureDeleted(result) => {
                 if let Ok(_) = result {
                     println!("capture has been deleted!");
-                    Task::perform(file::load_captures(), Message::CapturesLoaded)
+                    Task::batch([
+                        self.load_captures(Message::CapturesLoaded),
+                        self.load_trash(),
+                    ])
                 } else {
                     println!("capture has NOT been deleted!");
                     Task::none()
                 }
             }
-            Message::CapturesLoaded(result) => {
+            Message::MoveTopicInput(value) => {
+                self.capture.move_topic = value;
+                Task::none()
+            }
+            Message::MoveCapture => {
+                let Some(record) = self.capture.opened_capture.clone() else {
+                    return Task::none();
+                };
+                let topic = match TopicName::parse(&self.capture.move_topic) {
+                    Ok(topic) => topic,
+                    Err(e) => {
+                        self.ui_error = format!("Move failed: {}", e);
+                        return Task::perform(file::log(), Message::ShowError);
+                    }
+                };
+                if !self.editor.is_saved {
+                    self.ui_error = "Move failed: Save the capture before moving it.".to_string();
+                    return Task::perform(file::log(), Message::ShowError);
+                }
+                let root = self.vault_root();
+                let files = vec![record.header.file_name(), topic.file_name()];
+                self.perform_recorded(
+                    history::record(
+                        root.clone(),
+                        format!("move capture {} to {}", record.header.subject, topic),
+                        files,
+                        file::move_capture(root, record, topic.to_string()),
+                    ),
+                    Message::CaptureMoved,
+                )
+            }
+            Message::CaptureMoved(result) => match result {
+                Ok(record) => {
+                    self.capture.move_topic = String::new();
+                    Task::batch([
+                        self.load_captures(Message::CapturesLoaded),
+                        self.load_files(),
+                        Task::done(Message::CaptureOpened(Ok(record))),
+                    ])
+                }
+                Err(e) => {
+                    self.ui_error = format!("Move failed: {}", e);
+                    Task::perform(file::log(), Message::ShowError)
+                }
+            },
+            Message::CapturesLoaded(vault, result) => {
+                if vault != self.active_vault.name {
+                    return Task::none();
+                }
                 if let Ok(value) = result {
                     self.captures = value;
                 }
                 Task::none()
             }
-            Message::FilesLoaded(result) => {
+            Message::FilesLoaded(vault, result) => {
+                if vault != self.active_vault.name {
+                    return Task::none();
+                }
                 if let Ok(value) = result {
                     self.files = value;
                 }
                 Task::none()
             }
-            Message::CapturesReloaded(result) => {
+            Message::CapturesReloaded(vault, result) => {
+                if vault != self.active_vault.name {
+                    return Task::none();
+                }
                 if let Ok(value) = result {
                     self.captures = value;
                 }
@@ -241,10 +424,36 @@ impl Yoink {
             }
             Message::CaptureSearchChanged(value) => {
                 self.capture.search = value;
+                self.search_bodies()
+            }
+            Message::BodySearched(vault, search, result) => {
+                if vault != self.active_vault.name || search != self.capture.search {
+                    return Task::none();
+                }
+                match result {
+                    Ok(hits) => {
+                        let query = Query::parse(&search);
+                        self.capture.body_results = hits
+                            .into_iter()
+                            .filter(|hit| query.passes_filters(&hit.record))
+                            .collect();
+                    }
+                    Err(e) => eprintln!("Body search failed: {}", e),
+                }
                 Task::none()
             }
+            Message::BodyHitSelected(index) => {
+                if let Some(hit) = self.capture.body_results.get(index) {
+                    Task::perform(
+                        file::capture_opened(hit.record.clone()),
+                        Message::CaptureOpened,
+                    )
+                } else {
+                    Task::none()
+                }
+            }
             Message::FileSearchChanged(value) => {
-                println!("Searching file:: {}", value);
+                self.editor.search = value;
                 Task::none()
             }
             Message::CaptureTopicChanged(value) => {
@@ -257,6 +466,14 @@ impl Yoink {
                 self.update_submit_enabled();
                 Task::none()
             }
+            Message::CaptureTagsChanged(value) => {
+                self.capture.form_tags = value;
+                Task::none()
+            }
+            Message::CaptureTagSuggested(tag) => {
+                self.capture.form_tags = self.complete_tag(&tag);
+                Task::none()
+            }
             Message::CaptureFormContentChanged(action) => {
                 self.capture.form_content.perform(action);
                 self.update_submit_enabled();
@@ -275,109 +492,180 @@ impl Yoink {
                     self.ui_error = "Submission failed: Inputs cannot be null.".to_string();
                     Task::perform(file::log(), Message::ShowError)
                 } else {
-                    println!("Search: {}", self.capture.search);
-                    println!("Topic: {}", self.capture.form_topic);
-                    println!("Subject: {}", self.capture.form_subject);
-                    let form_content = self.capture.form_content.text();
-                    println!("{}", form_content);
-                    let file_name_prefix = "_";
-                    let file_name_ext = ".md";
-                    let form_topic = format!(
-                        "{}{}{}",
-                        file_name_prefix, self.capture.form_topic, file_name_ext
-                    );
-                    let spec_prefix = "<!--yoink::::";
-                    let spec_delimiter = "::::";
-                    let spec_suffix = "-->\n";
-                    let utc = Utc::now();
-                    let local_timestamp = utc.with_timezone(&Local).to_string();
-                    let local = &local_timestamp[..19].to_string();
-                    let spec_string = format!(
-                        "{}{}{}{}{}{}{}",
-                        spec_prefix,
-                        local,
-                        spec_delimiter,
-                        self.capture.form_topic,
-                        spec_delimiter,
-                        self.capture.form_subject,
-                        spec_suffix
-                    );
-                    let content_string = format!("{}{}", self.capture.form_content.text(), "\n");
-                    let capture_string = format!("{}{}", spec_string, content_string);
+                    match TopicName::parse(&self.capture.form_topic) {
+                        Err(e) => {
+                            self.ui_error =
+                                Yoink::invalid_topic_message(&self.capture.form_topic, e);
+                            Task::perform(file::log(), Message::ShowError)
+                        }
+                        Ok(topic) => {
+                            println!("Search: {}", self.capture.search);
+                            println!("Topic: {}", self.capture.form_topic);
+                            println!("Subject: {}", self.capture.form_subject);
+                            let form_content = self.capture.form_content.text();
+                            println!("{}", form_content);
+                            let mut header =
+                                CaptureHeader::new(topic.as_str(), &self.capture.form_subject);
+                            header.tags = capture_record::parse_tags(&self.capture.form_tags);
+                            let form_topic = header.file_name();
+                            let capture_string = format!("{}\n{}\n", header, form_content);
 
-                    Task::perform(
-                        file::append_file(form_topic, capture_string),
-                        Message::FileOpened,
-                    )
+                            let root = self.vault_root();
+                            self.perform_recorded(
+                                history::record(
+                                    root.clone(),
+                                    format!("submit capture {}", header.subject),
+                                    vec![form_topic.clone()],
+                                    file::append_file(root, form_topic, capture_string),
+                                ),
+                                Message::FileOpened,
+                            )
+                        }
+                    }
                 }
             }
             Message::CreateNewFile => {
-                println!("Creating {}", self.editor.new_file);
-                Task::perform(
-                    file::create_file(self.editor.new_file.clone()),
+                let topic = match TopicName::parse(&self.editor.new_file) {
+                    Ok(topic) => topic,
+                    Err(e) => {
+                        self.ui_error = Yoink::invalid_topic_message(&self.editor.new_file, e);
+                        return Task::perform(file::log(), Message::ShowError);
+                    }
+                };
+                println!("Creating {}", topic);
+                let root = self.vault_root();
+                self.perform_recorded(
+                    history::record(
+                        root.clone(),
+                        format!("create file {}", topic),
+                        vec![topic.file_name()],
+                        file::create_file(root, topic.to_string()),
+                    ),
                     Message::CreatingNewFile,
                 )
             }
-            Message::CreatingNewFile(result) => {
-                if let Ok(_) = result {
+            Message::CreatingNewFile(result) => match result {
+                Ok(_) => {
                     self.hide_helper();
-                    Task::perform(file::load_files(), Message::FilesLoaded)
-                } else {
+                    self.load_files()
+                }
+                Err(Error::FileExists) => {
+                    self.ui_error =
+                        format!("Create failed: {} already exists.", self.editor.new_file);
+                    self.existing_topic = TopicName::parse(&self.editor.new_file)
+                        .ok()
+                        .map(|topic| topic.file_name());
+                    Task::perform(file::log(), Message::ShowError)
+                }
+                Err(e) => {
                     println!("Failed CreatingNewFile");
-                    Task::none()
+                    self.ui_error = format!("Create failed: {}", e);
+                    Task::perform(file::log(), Message::ShowError)
+                }
+            },
+            Message::OpenExistingTopic => {
+                self.hide_helper();
+                match self.existing_topic.take() {
+                    Some(file) => Task::done(Message::FileSelected(file)),
+                    None => Task::none(),
                 }
             }
+            Message::HideExistingTopic => {
+                self.existing_topic = None;
+                Task::none()
+            }
             Message::UpdateCapture => {
+                // The save hotkey also saves a whole file or a preamble open in the editor.
+                if self.opened_file.is_some() {
+                    return Task::done(Message::UpdateFile);
+                }
+                if self.opened_preamble.is_some() {
+                    return Task::done(Message::UpdatePreamble);
+                }
                 if self.editor.editor_content.text().is_empty() {
                     self.ui_error = "Submission failed: Inputs cannot be null.".to_string();
                     Task::perform(file::log(), Message::ShowError)
-                } else {
-                    let before_content = &self.capture.before;
-                    let utc = Utc::now();
-                    let local_timestamp = utc.with_timezone(&Local).to_string();
-                    let timestamp = &local_timestamp[..19].to_string();
+                } else if let (Some(record), Some(section)) =
+                    (&self.capture.opened_capture, &self.capture.opened_section)
+                {
                     let file = self.capture.current_capture_file.clone();
-                    let trimmed_file = &file[1..file.len() - 3];
-                    let subject = self.capture.current_capture_subject.clone();
-                    let editor_header = format!(
-                        "<!--yoink::::{}::::{}::::{}-->\n",
-                        timestamp, trimmed_file, subject
-                    );
-                    let editor_content = self.editor.editor_content.text();
-                    let after_content = &self.capture.after;
+                    let mut header = record.header.clone();
+                    header.timestamp = CaptureHeader::timestamp_now();
+                    header.ensure_id();
+                    let section =
+                        section.with_body(header.clone(), &self.editor.editor_content.text());
                     let content = format!(
-                        "{}{}{}\n{}",
-                        before_content, editor_header, editor_content, after_content
+                        "{}{}{}",
+                        self.capture.before, section.text, self.capture.after
                     );
+                    // What the watcher will see for this capture once the save lands.
+                    self.capture.opened_section = Some(section);
 
-                    Task::perform(file::write_file(file, content), Message::FileWritten)
+                    let root = self.vault_root();
+                    self.perform_recorded(
+                        history::record(
+                            root.clone(),
+                            format!("update capture {}", header.subject),
+                            vec![file.clone()],
+                            file::write_file(root, file, content),
+                        ),
+                        Message::FileWritten,
+                    )
+                } else {
+                    self.ui_error = "Submission failed: No capture is open.".to_string();
+                    Task::perform(file::log(), Message::ShowError)
                 }
             }
             Message::UpdateFile => {
-                if self.editor.editor_content.text().is_empty() {
-                    self.ui_error = "Submission failed: Inputs cannot be null.".to_string();
+                self.editor.check_headers();
+                let Some(file) = self.opened_file.clone() else {
+                    self.ui_error = "Submission failed: No file is open.".to_string();
+                    return Task::perform(file::log(), Message::ShowError);
+                };
+                if !self.editor.broken_headers.is_empty() {
+                    self.ui_error = format!(
+                        "Submission failed: Broken capture headers on lines {}.",
+                        self.editor
+                            .broken_headers
+                            .iter()
+                            .map(usize::to_string)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    );
                     Task::perform(file::log(), Message::ShowError)
                 } else {
-                    let before_content = &self.capture.before;
-                    let utc = Utc::now();
-                    let local_timestamp = utc.with_timezone(&Local).to_string();
-                    let timestamp = &local_timestamp[..19].to_string();
-                    let file = self.capture.current_capture_file.clone();
-                    let trimmed_file = &file[1..file.len() - 3];
-                    let subject = self.capture.current_capture_subject.clone();
-                    let editor_header = format!(
-                        "<!--yoink::::{}::::{}::::{}-->\n",
-                        timestamp, trimmed_file, subject
-                    );
-                    let editor_content = self.editor.editor_content.text();
-                    let after_content = &self.capture.after;
-                    let content = format!(
-                        "{}{}{}\n{}",
-                        before_content, editor_header, editor_content, after_content
-                    );
-
-                    Task::perform(file::write_file(file, content), Message::FileWritten)
+                    let root = self.vault_root();
+                    self.perform_recorded(
+                        history::record(
+                            root.clone(),
+                            format!("update file {}", file),
+                            vec![file.clone()],
+                            file::write_file(root, file, self.editor.file_text()),
+                        ),
+                        Message::FileSaved,
+                    )
+                }
+            }
+            Message::FileSaved(result) => match result {
+                Ok(path) => {
+                    println!("Saved {}", path.display());
+                    self.editor.is_saved = true;
+                    self.editor.conflict = None;
+                    self.load_captures(Message::CapturesReloaded)
                 }
+                Err(e) => {
+                    self.ui_error = format!("Save failed: {}", e);
+                    Task::perform(file::log(), Message::ShowError)
+                }
+            },
+            Message::RepairHeaders => {
+                let (repaired, unrepaired) =
+                    capture_record::repair_headers(&self.editor.editor_content.text());
+                self.editor.editor_content = Content::with_text(&repaired);
+                self.editor.is_saved = false;
+                self.editor.broken_headers = unrepaired;
+                self.editor.refresh_preview();
+                Task::none()
             }
             Message::CreateFile => {
                 println!("Creating file..");
@@ -389,12 +677,21 @@ impl Yoink {
             }
             Message::DeleteFile(file_name) => {
                 println!("Deleting {}..", file_name);
-                Task::perform(file::delete_file(file_name), Message::FileDeleted)
+                let root = self.vault_root();
+                self.perform_recorded(
+                    history::record_trashed(
+                        root.clone(),
+                        format!("delete file {}", file_name),
+                        vec![file_name.clone()],
+                        file::delete_file(root, file_name),
+                    ),
+                    Message::FileDeleted,
+                )
             }
             Message::FileDeleted(result) => {
                 if let Ok(_) = result {
                     println!("file has been deleted");
-                    Task::perform(file::load_files(), Message::FilesLoaded)
+                    Task::batch([self.load_files(), self.load_trash()])
                 } else {
                     println!("file has NOT been deleted");
                     Task::none()
@@ -405,52 +702,378 @@ impl Yoink {
                     self.capture.updated_file = Some(path.to_string_lossy().to_string());
                     println!("Opened/Written to {}", path.display());
                 }
-                Task::perform(file::load_captures(), Message::CapturesLoaded)
+                self.load_captures(Message::CapturesLoaded)
             }
             Message::CaptureOpened(result) => {
-                if let Ok((timestamp, path, subject)) = result {
-                    self.capture.opened_capture = Some((timestamp, path, subject));
-
-                    if let Some((ref timestamp_str, ref path_str, ref subject_str)) =
-                        self.capture.opened_capture
-                    {
-                        self.capture.current_capture_timestamp = timestamp_str.to_string();
-                        self.capture.current_capture_file = path_str.to_string_lossy().to_string();
-                        self.capture.current_capture_subject = subject_str.to_string();
-                        self.capture.current_capture = format!(
-                            "{} {} {}",
-                            self.capture.current_capture_timestamp,
-                            self.capture.current_capture_file,
-                            self.capture.current_capture_subject,
-                        );
+                if let Ok(record) = result {
+                    self.opened_file = None;
+                    self.opened_preamble = None;
+                    self.capture.current_capture_file = record.header.file_name();
+                    self.capture.current_capture = format!(
+                        "{} {} {}",
+                        record.header.timestamp,
+                        self.capture.current_capture_file,
+                        record.header.subject,
+                    );
+                    self.capture.opened_capture = Some(record);
+                }
+                match &self.capture.opened_capture {
+                    Some(record) if self.capture.current_capture != "Editor..".to_string() => {
+                        let root = self.vault_root();
+                        let header = record.header.clone();
+                        Task::batch([
+                            Task::perform(
+                                async move { file::read_capture(&root, &header).await },
+                                Message::SetInitialEditorText,
+                            ),
+                            Task::perform(async {}, |_| Message::Edit),
+                        ])
                     }
+                    _ => Task::none(),
                 }
-                if self.capture.current_capture != "Editor..".to_string() {
-                    let timestamp = self.capture.current_capture_timestamp.clone();
-                    let subject = self.capture.current_capture_subject.clone();
-                    Task::batch([
-                        Task::perform(
-                            async move { file::read_capture(&timestamp, "_test.md", &subject).await },
-                            Message::SetInitialEditorText,
-                        ),
-                        Task::perform(async {}, |_| Message::Edit),
-                    ])
+            }
+            Message::EditorFileOpened(file_name, result) => {
+                match result {
+                    Ok(content) => {
+                        // Whole-file mode: the file is edited as is, no capture is open.
+                        self.capture.opened_capture = None;
+                        self.capture.opened_section = None;
+                        self.capture.current_capture_file = String::new();
+                        self.capture.before = String::new();
+                        self.capture.after = String::new();
+                        self.editor.open_file_text(&content);
+                        self.opened_preamble = None;
+                        self.opened_file = Some(file_name);
+                    }
+                    Err(e) => eprintln!("Failed to open {}.\nUnderlying error: {}", file_name, e),
+                }
+                Task::none()
+            }
+            Message::PreambleSelected(file_name) => {
+                self.show_topics = false;
+                Task::perform(
+                    file::read_preamble(self.vault_root(), file_name.clone()),
+                    move |result| Message::PreambleOpened(file_name.clone(), result),
+                )
+            }
+            Message::PreambleOpened(file_name, result) => {
+                match result {
+                    Ok(preamble) => {
+                        // The preamble is edited on its own; the captures stay as they are.
+                        self.capture.opened_capture = None;
+                        self.capture.opened_section = None;
+                        self.capture.current_capture_file = String::new();
+                        self.capture.before = String::new();
+                        self.capture.after = String::new();
+                        self.opened_file = None;
+                        self.editor.open_file_text(&preamble);
+                        self.opened_preamble = Some(file_name);
+                    }
+                    Err(e) => eprintln!(
+                        "Failed to open the preamble of {}.\nUnderlying error: {}",
+                        file_name, e
+                    ),
+                }
+                Task::none()
+            }
+            Message::UpdatePreamble => {
+                let Some(file) = self.opened_preamble.clone() else {
+                    self.ui_error = "Submission failed: No preamble is open.".to_string();
+                    return Task::perform(file::log(), Message::ShowError);
+                };
+                let preamble = self.editor.file_text();
+                self.editor.check_headers();
+                if !TopicFile::parse(&preamble).sections.is_empty()
+                    || !self.editor.broken_headers.is_empty()
+                {
+                    self.ui_error =
+                        "Submission failed: A preamble cannot contain capture headers.".to_string();
+                    return Task::perform(file::log(), Message::ShowError);
+                }
+                let root = self.vault_root();
+                self.perform_recorded(
+                    history::record(
+                        root.clone(),
+                        format!("update preamble of {}", file),
+                        vec![file.clone()],
+                        file::write_preamble(root, file, preamble),
+                    ),
+                    Message::FileSaved,
+                )
+            }
+            Message::SwitchVault(vault) => {
+                if vault == self.active_vault {
+                    return Task::none();
+                }
+                println!("Switching to vault {}", vault);
+                self.active_vault = vault;
+                self.captures = Vec::new();
+                self.files = Vec::new();
+                self.opened_file = None;
+                self.opened_preamble = None;
+                self.capture.opened_capture = None;
+                self.capture.opened_section = None;
+                self.capture.body_results = Vec::new();
+                self.capture.current_capture = "Editor..".to_string();
+                self.capture.current_capture_file = String::new();
+                self.capture.before = String::new();
+                self.capture.after = String::new();
+                self.capture.tag_filter = None;
+                self.capture.move_topic = String::new();
+                self.tag_rename_from = None;
+                self.topic_edit_from = None;
+                self.topic_collisions = Vec::new();
+                self.existing_topic = None;
+                self.editor = Editor::new();
+                self.history.clear();
+                self.load_vault()
+            }
+            Message::TopicsChanged(vault, files) => {
+                if vault != self.active_vault.name {
+                    return Task::none();
+                }
+                println!("Changed on disk: {}", files.join(", "));
+                self.reindex_topics(files)
+            }
+            Message::TopicsReindexed(vault, result) => {
+                if vault != self.active_vault.name {
+                    return Task::none();
+                }
+                match result {
+                    Ok(topics) => {
+                        for (file_name, content) in topics {
+                            self.replace_topic_captures(&file_name, content.as_deref());
+                            if file_name == self.capture.current_capture_file {
+                                self.sync_opened_capture(content.as_deref());
+                            }
+                            if self.opened_file.as_ref() == Some(&file_name) {
+                                self.sync_opened_file(content.as_deref());
+                            }
+                        }
+                    }
+                    Err(e) => eprintln!("Failed to re-index changed topics: {}", e),
+                }
+                Task::none()
+            }
+            Message::ViewTrash => {
+                self.show_trash = true;
+                self.load_trash()
+            }
+            Message::HideTrash => {
+                self.show_trash = false;
+                Task::none()
+            }
+            Message::TrashLoaded(vault, result) => {
+                if vault != self.active_vault.name {
+                    return Task::none();
+                }
+                if let Ok(value) = result {
+                    self.trash = value;
+                }
+                Task::none()
+            }
+            Message::RestoreTrash(index) => {
+                if let Some(item) = self.trash.get(index) {
+                    println!("Restoring {} to {}", item.id, item.original_file);
+                    Task::perform(
+                        trash::restore(self.vault_root(), item.clone()),
+                        Message::TrashRestored,
+                    )
                 } else {
                     Task::none()
                 }
             }
-            Message::EditorFileOpened(result) => {
-                if let Ok(lines) = result {
-                    self.opened_file = lines.clone();
-                    let mut editor_content: String = String::new();
-                    for line in lines {
-                        editor_content.push_str(&line);
+            Message::TrashRestored(result) => {
+                if let Err(e) = result {
+                    self.ui_error = format!("Restore failed: {}", e);
+                    Task::perform(file::log(), Message::ShowError)
+                } else {
+                    self.load_vault()
+                }
+            }
+            Message::ViewTags => {
+                self.show_tags = true;
+                Task::none()
+            }
+            Message::HideTags => {
+                self.show_tags = false;
+                self.tag_rename_from = None;
+                Task::none()
+            }
+            Message::TagRenameSelected(tag) => {
+                self.tag_rename_to = tag.clone();
+                self.tag_rename_from = Some(tag);
+                Task::none()
+            }
+            Message::TagRenameInput(value) => {
+                self.tag_rename_to = value;
+                Task::none()
+            }
+            Message::RenameTag => {
+                let Some(from) = self.tag_rename_from.clone() else {
+                    return Task::none();
+                };
+                let Some(to) = capture_record::normalize_tag(&self.tag_rename_to) else {
+                    self.ui_error =
+                        format!("Rename failed: {} is not a valid tag.", self.tag_rename_to);
+                    return Task::perform(file::log(), Message::ShowError);
+                };
+                if from == to {
+                    return Task::none();
+                }
+                println!("Renaming tag {} to {}", from, to);
+                let root = self.vault_root();
+                let files = self.topic_files();
+                self.perform_recorded(
+                    history::record(
+                        root.clone(),
+                        format!("rename tag {} to {}", from, to),
+                        files.clone(),
+                        file::rename_tag(root, files, from, to),
+                    ),
+                    Message::TagRenamed,
+                )
+            }
+            Message::TagRenamed(result) => match result {
+                Ok(_) => {
+                    if let Some(from) = self.tag_rename_from.take() {
+                        if self.capture.tag_filter.as_ref() == Some(&from) {
+                            self.capture.tag_filter =
+                                capture_record::normalize_tag(&self.tag_rename_to);
+                        }
+                    }
+                    self.load_captures(Message::CapturesLoaded)
+                }
+                Err(e) => {
+                    self.ui_error = format!("Rename failed: {}", e);
+                    Task::perform(file::log(), Message::ShowError)
+                }
+            },
+            Message::ViewTopics => {
+                self.show_topics = true;
+                self.topic_collisions = Vec::new();
+                Task::none()
+            }
+            Message::HideTopics => {
+                self.show_topics = false;
+                self.topic_edit_from = None;
+                Task::none()
+            }
+            Message::TopicEditSelected(topic) => {
+                self.topic_edit_to = topic.clone();
+                self.topic_edit_from = Some(topic);
+                self.topic_collisions = Vec::new();
+                Task::none()
+            }
+            Message::TopicEditInput(value) => {
+                self.topic_edit_to = value;
+                Task::none()
+            }
+            Message::RenameTopic | Message::MergeTopic => {
+                let Some(from) = self.topic_edit_from.clone() else {
+                    return Task::none();
+                };
+                let to = self.topic_edit_to.trim().to_string();
+                if to.is_empty() || to == from {
+                    return Task::none();
+                }
+                let target = match TopicName::parse(&to) {
+                    Ok(target) => target,
+                    Err(e) => {
+                        self.ui_error = Yoink::invalid_topic_message(&to, e);
+                        return Task::perform(file::log(), Message::ShowError);
+                    }
+                };
+                let root = self.vault_root();
+                let files = vec![format!("_{}.md", from), target.file_name()];
+                if let Message::RenameTopic = message {
+                    println!("Renaming topic {} to {}", from, to);
+                    self.perform_recorded(
+                        history::record(
+                            root.clone(),
+                            format!("rename topic {} to {}", from, to),
+                            files,
+                            file::rename_topic(root, from, to),
+                        ),
+                        Message::TopicRenamed,
+                    )
+                } else {
+                    println!("Merging topic {} into {}", from, to);
+                    self.perform_recorded(
+                        history::record(
+                            root.clone(),
+                            format!("merge topic {} into {}", from, to),
+                            files,
+                            file::merge_topic(root, from, to, settings::get().legacy_timezone),
+                        ),
+                        Message::TopicMerged,
+                    )
+                }
+            }
+            Message::TopicRenamed(result) => match result {
+                Ok(_) => self.topic_changed(&[]),
+                Err(e) => {
+                    self.ui_error = format!("Rename failed: {}", e);
+                    Task::perform(file::log(), Message::ShowError)
+                }
+            },
+            Message::TopicMerged(result) => match result {
+                Ok(collisions) => {
+                    let task = self.topic_changed(&collisions);
+                    self.topic_collisions = collisions;
+                    task
+                }
+                Err(e) => {
+                    self.ui_error = format!("Merge failed: {}", e);
+                    Task::perform(file::log(), Message::ShowError)
+                }
+            },
+            Message::TagFilterChanged(tag) => {
+                self.capture.tag_filter = tag;
+                Task::none()
+            }
+            Message::Journaled(entry) => {
+                if let Some(entry) = entry {
+                    let label = entry.label.clone();
+                    if self.history.push(entry) {
+                        println!("Journaled {}", label);
+                    } else {
+                        self.ui_error = format!("{} changed too much to be undone.", label);
+                        return Task::perform(file::log(), Message::ShowError);
                     }
-                    self.editor.editor_content = Content::with_text(&editor_content);
-                    //TODO: add filename and meta to editor header
                 }
                 Task::none()
             }
+            Message::Undo => {
+                if let Some(entry) = self.history.take_undo() {
+                    println!("Undoing {}", entry.label);
+                    Task::perform(history::undo(entry.clone()), move |result| {
+                        Message::Undone(entry.clone(), result)
+                    })
+                } else {
+                    Task::none()
+                }
+            }
+            Message::Redo => {
+                if let Some(entry) = self.history.take_redo() {
+                    println!("Redoing {}", entry.label);
+                    Task::perform(history::redo(entry.clone()), move |result| {
+                        Message::Redone(entry.clone(), result)
+                    })
+                } else {
+                    Task::none()
+                }
+            }
+            Message::Undone(entry, result) => {
+                let task = self.history_applied(format!("Undo {}", entry.label), &result);
+                self.history.undone(entry, &result);
+                task
+            }
+            Message::Redone(entry, result) => {
+                let task = self.history_applied(format!("Redo {}", entry.label), &result);
+                self.history.redone(entry, &result);
+                task
+            }
             Message::Ignore => {
                 println!("it's working");
                 Task::none()
@@ -496,9 +1119,29 @@ impl Yoink {
     }
 
     fn view(&self) -> Element<Message> {
-        let content = self.view_editor_pane();
-        if self.modal_helper {
-            let capture = self.view_capture_sidebar();
+        let editor: Element<Message> = if self.editor.preview_mode == PreviewMode::SideBySide {
+            row![self.view_editor_pane(), self.view_preview_pane()].into()
+        } else {
+            self.view_editor_pane()
+        };
+        let content: Element<Message> = row![self.view_editor_sidebar(), editor].into();
+        if self.show_trash {
+            let trash = container(self.view_trash());
+            Yoink::modal(content, trash, Message::HideTrash).into()
+        } else if self.existing_topic.is_some() {
+            let existing = container(self.view_existing_topic());
+            Yoink::modal(content, existing, Message::HideExistingTopic).into()
+        } else if self.show_topics {
+            let topics = container(self.view_topics());
+            Yoink::modal(content, topics, Message::HideTopics).into()
+        } else if self.show_tags {
+            let tags = container(self.view_tags());
+            Yoink::modal(content, tags, Message::HideTags).into()
+        } else if self.modal_helper {
+            let capture = row![
+                self.view_capture_sidebar(),
+                container(self.view_capture_pane()).width(400).height(520)
+            ];
             let helper = container(capture);
             Yoink::modal(content, helper, Message::ViewModalHelper).into()
         } else {
@@ -512,6 +1155,7 @@ impl Yoink {
         //             PaneState::CaptureFormPane => self.view_capture_pane(),
         //             PaneState::EditorSidebarPane => self.view_editor_sidebar(),
         //             PaneState::EditorPane => self.view_editor_pane(),
+        //             PaneState::PreviewPane => self.view_preview_pane(),
         //         };
         //
         //         pane_grid::Content::new(content)
@@ -521,6 +1165,7 @@ impl Yoink {
         //             PaneState::CaptureFormPane => self.view_capture_pane(),
         //             PaneState::EditorSidebarPane => self.view_editor_sidebar(),
         //             PaneState::EditorPane => self.view_editor_pane(),
+        //             PaneState::PreviewPane => self.view_preview_pane(),
         //         };
         //
         //         pane_grid::Content::new(content)
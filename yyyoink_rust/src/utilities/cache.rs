@@ -0,0 +1,265 @@
+use super::file;
+use super::index::Signature;
+use crate::capture::capture_record::{self, CaptureHeader, CaptureRecord};
+use crate::enums::error::Error;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+const CACHE_DIR: &str = ".cache";
+const CACHE_FILE: &str = "captures.sqlite";
+/// Bumped whenever the tables change; an older cache is dropped and rebuilt from the files.
+const SCHEMA_VERSION: i64 = 1;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS files (
+    path TEXT PRIMARY KEY,
+    size INTEGER NOT NULL,
+    mtime INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS captures (
+    path TEXT NOT NULL REFERENCES files(path) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    header TEXT NOT NULL,
+    tags TEXT NOT NULL,
+    PRIMARY KEY (path, position)
+);
+PRAGMA foreign_keys = ON;
+";
+
+fn cache_path(root: &Path) -> PathBuf {
+    root.join(CACHE_DIR).join(CACHE_FILE)
+}
+
+fn sql_error(e: rusqlite::Error) -> Error {
+    eprintln!("Capture cache error.\nUnderlying error: {}", e);
+    Error::IoError(ErrorKind::Other)
+}
+
+fn open(root: &Path) -> Result<Connection, Error> {
+    std::fs::create_dir_all(root.join(CACHE_DIR)).map_err(|e| Error::IoError(e.kind()))?;
+    let connection = Connection::open(cache_path(root)).map_err(sql_error)?;
+    let version: i64 = connection
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(sql_error)?;
+    if version != SCHEMA_VERSION {
+        connection
+            .execute_batch("DROP TABLE IF EXISTS captures; DROP TABLE IF EXISTS files;")
+            .map_err(sql_error)?;
+        connection
+            .pragma_update(None, "user_version", SCHEMA_VERSION)
+            .map_err(sql_error)?;
+    }
+    connection.execute_batch(SCHEMA).map_err(sql_error)?;
+    Ok(connection)
+}
+
+/// Capture headers of `file_names`, taken from the cache for files whose size and modification
+/// time are unchanged and re-parsed from disk for the rest. Files no longer listed are dropped
+/// from the cache. The markdown files stay the source of truth.
+fn load_blocking(root: &Path, file_names: &[String]) -> Result<Vec<CaptureRecord>, Error> {
+    let mut connection = open(root)?;
+    let transaction = connection.transaction().map_err(sql_error)?;
+    let mut captures: Vec<CaptureRecord> = Vec::new();
+    let mut parsed = 0;
+
+    for file_name in file_names {
+        let path = root.join(file_name);
+        let meta = std::fs::metadata(&path).map_err(|e| Error::IoError(e.kind()))?;
+        let Some(stamp) = Signature::from_metadata(&meta) else {
+            return Err(Error::IoError(ErrorKind::InvalidData));
+        };
+        let (size, mtime) = (stamp.len as i64, stamp.modified as i64);
+
+        let cached: Option<(i64, i64)> = transaction
+            .query_row(
+                "SELECT size, mtime FROM files WHERE path = ?1",
+                params![file_name],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(sql_error)?;
+
+        if cached == Some((size, mtime)) {
+            let mut statement = transaction
+                .prepare_cached(
+                    "SELECT header, tags FROM captures WHERE path = ?1 ORDER BY position",
+                )
+                .map_err(sql_error)?;
+            let rows = statement
+                .query_map(params![file_name], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+                })
+                .map_err(sql_error)?;
+            for row in rows {
+                let (header, tags) = row.map_err(sql_error)?;
+                if let Some(header) = CaptureHeader::parse(&header) {
+                    captures.push(CaptureRecord {
+                        header,
+                        file_name: file_name.clone(),
+                        tags: capture_record::parse_tags(&tags),
+                    });
+                }
+            }
+            continue;
+        }
+
+        let bytes = std::fs::read(&path).map_err(|e| Error::IoError(e.kind()))?;
+        let content =
+            String::from_utf8(bytes).map_err(|_| Error::IoError(ErrorKind::InvalidData))?;
+        let records = file::capture_records(file_name, &content);
+        parsed += 1;
+
+        transaction
+            .execute("DELETE FROM files WHERE path = ?1", params![file_name])
+            .map_err(sql_error)?;
+        transaction
+            .execute(
+                "INSERT INTO files (path, size, mtime) VALUES (?1, ?2, ?3)",
+                params![file_name, size, mtime],
+            )
+            .map_err(sql_error)?;
+        for (position, record) in records.iter().enumerate() {
+            transaction
+                .execute(
+                    "INSERT INTO captures (path, position, header, tags) VALUES (?1, ?2, ?3, ?4)",
+                    params![
+                        file_name,
+                        position as i64,
+                        record.header.to_string(),
+                        record.tags.join(",")
+                    ],
+                )
+                .map_err(sql_error)?;
+        }
+        captures.extend(records);
+    }
+
+    let mut statement = transaction
+        .prepare("SELECT path FROM files")
+        .map_err(sql_error)?;
+    let stale: Vec<String> = statement
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(sql_error)?
+        .filter_map(Result::ok)
+        .filter(|path| !file_names.contains(path))
+        .collect();
+    drop(statement);
+    for path in &stale {
+        transaction
+            .execute("DELETE FROM files WHERE path = ?1", params![path])
+            .map_err(sql_error)?;
+    }
+
+    transaction.commit().map_err(sql_error)?;
+    if parsed > 0 || !stale.is_empty() {
+        println!(
+            "Capture cache: re-parsed {} files, dropped {}.",
+            parsed,
+            stale.len()
+        );
+    }
+    Ok(captures)
+}
+
+pub async fn load_captures(
+    root: PathBuf,
+    file_names: Vec<String>,
+) -> Result<Vec<CaptureRecord>, Error> {
+    tokio::task::spawn_blocking(move || load_blocking(&root, &file_names))
+        .await
+        .map_err(|e| {
+            eprintln!("Capture cache task failed.\nUnderlying error: {}", e);
+            Error::IoError(ErrorKind::Other)
+        })?
+}
+
+/// Throws the cache away and fills it again from the topic files of `root`.
+/// Returns how many captures were cached.
+pub fn rebuild(root: &Path) -> Result<usize, Error> {
+    match std::fs::remove_file(cache_path(root)) {
+        Err(e) if e.kind() != ErrorKind::NotFound => return Err(Error::IoError(e.kind())),
+        _ => {}
+    }
+
+    let mut file_names: Vec<String> = Vec::new();
+    for entry in std::fs::read_dir(root).map_err(|e| Error::IoError(e.kind()))? {
+        let name = entry
+            .map_err(|e| Error::IoError(e.kind()))?
+            .file_name()
+            .to_string_lossy()
+            .to_string();
+        if name.starts_with('_') && name.ends_with(".md") {
+            file_names.push(name);
+        }
+    }
+    file_names.sort();
+
+    Ok(load_blocking(root, &file_names)?.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WORK: &str =
+        "<!--yoink::::2025-04-01 09:00:00::::work::::standup::::id=01JQCACHE00000000000000001::::tags=team-->
+notes #daily
+<!--yoink::::2025-04-02 09:00:00::::work::::legacy-->
+more notes
+";
+
+    fn vault() -> PathBuf {
+        let root = std::env::temp_dir().join(format!("yyyoink-cache-{}", CaptureHeader::new_id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("_work.md"), WORK).unwrap();
+        root
+    }
+
+    #[test]
+    fn serves_unchanged_files_from_the_cache() {
+        let root = vault();
+        let files = vec!["_work.md".to_string()];
+        let first = load_blocking(&root, &files).unwrap();
+        assert_eq!(first.len(), 2);
+        assert_eq!(first[0].tags, ["daily", "team"]);
+
+        // Same stamp, so the cached headers win even though the file content differs.
+        let meta = std::fs::metadata(root.join("_work.md")).unwrap();
+        let swapped = WORK.replace("standup", "standby");
+        std::fs::write(root.join("_work.md"), &swapped).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(root.join("_work.md"))
+            .unwrap()
+            .set_modified(meta.modified().unwrap())
+            .unwrap();
+        assert_eq!(load_blocking(&root, &files).unwrap(), first);
+
+        // A changed stamp is re-parsed.
+        std::fs::write(root.join("_work.md"), format!("{}\n", swapped)).unwrap();
+        let reparsed = load_blocking(&root, &files).unwrap();
+        assert_eq!(reparsed[0].header.subject, "standby");
+        assert_eq!(reparsed[1].header.id, None);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn rebuild_starts_from_scratch_and_drops_removed_files() {
+        let root = vault();
+        std::fs::write(root.join("_home.md"), WORK.replace("work", "home")).unwrap();
+        assert_eq!(rebuild(&root).unwrap(), 4);
+
+        std::fs::remove_file(root.join("_home.md")).unwrap();
+        assert_eq!(
+            load_blocking(&root, &["_work.md".to_string()])
+                .unwrap()
+                .len(),
+            2
+        );
+        assert_eq!(rebuild(&root).unwrap(), 2);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,12 +1,21 @@
+#[cfg(feature = "sqlite-cache")]
+use super::cache;
+use super::index;
 use super::tool;
+use super::trash::{self, TrashItem, TrashKind};
+use crate::capture::capture_record::{
+    self, AssumedZone, CaptureHeader, CaptureRecord, CaptureSection, TopicFile,
+};
+use crate::capture::topic_name::TopicName;
 use crate::enums::error::Error;
 use crate::enums::message::Message;
 use iced::keyboard;
 use std::ffi::OsString;
 use std::io::ErrorKind;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tokio::fs;
 use tokio::io::AsyncWriteExt;
+use ulid::Ulid;
 
 pub async fn log() -> Result<String, Error> {
     let success = true;
@@ -19,9 +28,9 @@ pub async fn log() -> Result<String, Error> {
     }
 }
 
-pub async fn load_captures() -> Result<Vec<Vec<String>>, Error> {
+pub async fn load_captures(root: PathBuf) -> Result<Vec<CaptureRecord>, Error> {
     let mut files: Vec<String> = Vec::new();
-    match get_files().await {
+    match get_files(&root).await {
         Ok(file_names) => {
             for file in file_names {
                 files.push(file);
@@ -32,26 +41,27 @@ pub async fn load_captures() -> Result<Vec<Vec<String>>, Error> {
         }
     }
 
-    let mut captures: Option<Vec<Vec<String>>> = None;
+    index::sync(&root, &files).await;
+
+    #[cfg(feature = "sqlite-cache")]
+    match cache::load_captures(root.clone(), files.clone()).await {
+        Ok(captures) if !captures.is_empty() => return Ok(captures),
+        Ok(_) => return Err(Error::IoError(ErrorKind::InvalidData)),
+        Err(e) => eprintln!(
+            "Capture cache unavailable, reading files.\nUnderlying error: {}",
+            e
+        ),
+    }
+
+    let mut captures: Option<Vec<CaptureRecord>> = None;
     for file in files {
         let file_name: &str = file.as_ref();
 
-        match read_file(file_name).await {
+        match read_file(&root, file_name).await {
             Ok(lines) => {
-                for line in &lines {
-                    // TODO: Check for various-sized initial input strings
-                    if line.len() > 16 && &line[..4] == "<!--" && &line[line.len() - 3..] == "-->" {
-                        let chars: Vec<char> = line.chars().collect();
-                        let unwrapped_line: String = chars[4..chars.len() - 3].iter().collect();
-                        let parts: Vec<String> = unwrapped_line
-                            .split("::::")
-                            .map(|s| s.to_string())
-                            .collect();
-
-                        captures
-                            .get_or_insert(vec![])
-                            .push(parts.into_iter().skip(1).collect());
-                    }
+                let records = capture_records(&file, &lines.join("\n"));
+                if !records.is_empty() {
+                    captures.get_or_insert(vec![]).extend(records);
                 }
             }
             Err(e) => {
@@ -67,10 +77,41 @@ pub async fn load_captures() -> Result<Vec<Vec<String>>, Error> {
     }
 }
 
-async fn get_files() -> Result<Vec<String>, Error> {
-    let source_dir = tool::source_dir();
-    let source_dir_ref: &str = &source_dir;
-    let mut entries = tokio::fs::read_dir(source_dir_ref)
+/// Lists the captures of one topic file with their tags.
+pub fn capture_records(file_name: &str, content: &str) -> Vec<CaptureRecord> {
+    capture_record::parse_sections(content)
+        .iter()
+        .map(|section| CaptureRecord::new(section, file_name))
+        .collect()
+}
+
+/// Reads topic files that changed on disk. A file that no longer exists comes back as `None`.
+pub async fn read_topics(
+    root: PathBuf,
+    files: Vec<String>,
+) -> Result<Vec<(String, Option<String>)>, Error> {
+    let mut topics: Vec<(String, Option<String>)> = Vec::new();
+    for file in files {
+        let path = tool::vault_path(&root, &file)?;
+        index::update_file(&root, &file).await;
+        match fs::read_to_string(&path).await {
+            Ok(content) => topics.push((file, Some(content))),
+            Err(e) if e.kind() == ErrorKind::NotFound => topics.push((file, None)),
+            Err(e) => {
+                eprintln!(
+                    "Failed to read {}.\nUnderlying error: {}",
+                    path.display(),
+                    e
+                );
+                return Err(Error::IoError(e.kind()));
+            }
+        }
+    }
+    Ok(topics)
+}
+
+async fn get_files(root: &Path) -> Result<Vec<String>, Error> {
+    let mut entries = tokio::fs::read_dir(root)
         .await
         .map_err(|e| Error::IoError(e.kind()))?;
 
@@ -96,10 +137,8 @@ async fn get_files() -> Result<Vec<String>, Error> {
     Ok(file_names)
 }
 
-pub async fn load_files() -> Result<Vec<String>, Error> {
-    let source_dir = tool::source_dir();
-    let source_dir_ref: &str = &source_dir;
-    let mut entries = tokio::fs::read_dir(source_dir_ref)
+pub async fn load_files(root: PathBuf) -> Result<Vec<String>, Error> {
+    let mut entries = tokio::fs::read_dir(&root)
         .await
         .map_err(|e| Error::IoError(e.kind()))?;
 
@@ -132,39 +171,85 @@ pub async fn load_files() -> Result<Vec<String>, Error> {
     Ok(file_names)
 }
 
-async fn read_file(file_name: &str) -> Result<Vec<String>, Error> {
+async fn read_file(root: &Path, file_name: &str) -> Result<Vec<String>, Error> {
+    let string = read_text(root, file_name).await?;
+    Ok(string.lines().map(|s| s.to_string()).collect())
+}
+
+/// Reads a topic file exactly as it is on disk, line endings included.
+async fn read_text(root: &Path, file_name: &str) -> Result<String, Error> {
     // TODO:Remove this paranoid file check.
     // Attempt the read and handle error if it occurs due to non-existant file.
-    let capture_path = tool::source_path(file_name.to_string());
-    let capture_path_ref: &str = &capture_path;
-    let (is_file, path) = file_exists(capture_path_ref).await;
+    let capture_path = tool::vault_path(root, file_name)?;
+    let (is_file, path) = file_exists(capture_path).await;
 
     if is_file {
         let bytes = tokio::fs::read(&path)
             .await
             .map_err(|e| Error::IoError(e.kind()))?;
-        if let Ok(string) = String::from_utf8(bytes.clone()) {
-            let lines: Vec<String> = string.lines().map(|s| s.to_string()).collect();
-            Ok(lines)
-        } else {
-            Err(Error::IoError(ErrorKind::InvalidData))
-        }
+        String::from_utf8(bytes).map_err(|_| Error::IoError(ErrorKind::InvalidData))
     } else {
         eprintln!("Failed to read file. File does not exist.");
         Err(Error::FileNotFound)
     }
 }
 
-pub async fn append_file(capture_file: String, capture_string: String) -> Result<PathBuf, Error> {
-    let capture_path = tool::source_path(capture_file);
-    let capture_path_ref: &str = &capture_path;
-    let (is_file, path) = file_exists(capture_path_ref).await;
+/// How a file opened in the editor ends its lines, so saving writes them back the same way.
+/// A file mixing both endings is written back with the ending of its first line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineEndings {
+    pub crlf: bool,
+    pub final_newline: bool,
+}
+
+impl Default for LineEndings {
+    fn default() -> Self {
+        Self {
+            crlf: false,
+            final_newline: true,
+        }
+    }
+}
+
+impl LineEndings {
+    pub fn detect(content: &str) -> Self {
+        Self {
+            crlf: content
+                .find('\n')
+                .is_some_and(|end| content[..end].ends_with('\r')),
+            final_newline: content.is_empty() || content.ends_with('\n'),
+        }
+    }
+
+    /// Turns editor text, which uses `\n` and always ends with one, back into the file's form.
+    pub fn restore(&self, text: &str) -> String {
+        let text = text.replace("\r\n", "\n");
+        let text = match text.strip_suffix('\n') {
+            Some(trimmed) if !self.final_newline => trimmed,
+            _ => &text,
+        };
+        if self.crlf {
+            text.replace('\n', "\r\n")
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+pub async fn append_file(
+    root: PathBuf,
+    capture_file: String,
+    capture_string: String,
+) -> Result<PathBuf, Error> {
+    let capture_path = tool::vault_path(&root, &capture_file)?;
+    let (is_file, path) = file_exists(capture_path).await;
 
     if !is_file {
         let capture_bytes: &[u8] = capture_string.as_bytes();
-        match tokio::fs::write(&path, capture_bytes).await {
+        match write_atomic(&path, capture_bytes).await {
             Ok(_) => {
                 println!("Wrote to file {}.", path.display());
+                index::update_file(&root, &capture_file).await;
                 Ok(path)
             }
             Err(e) => {
@@ -205,7 +290,7 @@ pub async fn append_file(capture_file: String, capture_string: String) -> Result
             })?;
 
         let mut writer = tokio::io::BufWriter::new(file);
-        let _ = writer.write_all(capture_bytes).await.map_err(|e| {
+        writer.write_all(capture_bytes).await.map_err(|e| {
             let err = match e.kind() {
                 ErrorKind::PermissionDenied => Error::PermissionDenied,
                 ErrorKind::NotFound => Error::FileNotFound,
@@ -218,7 +303,7 @@ pub async fn append_file(capture_file: String, capture_string: String) -> Result
                 e
             );
             err
-        });
+        })?;
 
         writer.flush().await.map_err(|e| {
             let err = match e.kind() {
@@ -235,19 +320,39 @@ pub async fn append_file(capture_file: String, capture_string: String) -> Result
             err
         })?;
 
+        writer.into_inner().sync_all().await.map_err(|e| {
+            let err = match e.kind() {
+                ErrorKind::PermissionDenied => Error::PermissionDenied,
+                ErrorKind::NotFound => Error::FileNotFound,
+                kind => Error::IoError(kind),
+            };
+            eprintln!(
+                "Failed to sync file {}: {}\nUnderlying error: {}",
+                path.display(),
+                err,
+                e
+            );
+            err
+        })?;
+
+        index::update_file(&root, &capture_file).await;
         Ok(path)
     }
 }
 
-pub async fn write_file(capture_file: String, capture_string: String) -> Result<PathBuf, Error> {
-    let capture_path = tool::source_path(capture_file);
-    let capture_path_ref: &str = &capture_path;
-    let (_is_file, path) = file_exists(capture_path_ref).await;
+pub async fn write_file(
+    root: PathBuf,
+    capture_file: String,
+    capture_string: String,
+) -> Result<PathBuf, Error> {
+    let capture_path = tool::vault_path(&root, &capture_file)?;
+    let (_is_file, path) = file_exists(capture_path).await;
 
     let capture_bytes: &[u8] = capture_string.as_bytes();
-    match tokio::fs::write(&path, capture_bytes).await {
+    match write_atomic(&path, capture_bytes).await {
         Ok(_) => {
             println!("Wrote to file {}.", path.display());
+            index::update_file(&root, &capture_file).await;
             Ok(path)
         }
         Err(e) => {
@@ -267,188 +372,305 @@ pub async fn write_file(capture_file: String, capture_string: String) -> Result<
     }
 }
 
+/// Replaces `path` with `bytes` so that a crash or full disk never leaves it half-written.
+/// The bytes go to a temp file in the same directory, which is synced, given the original
+/// file's permissions and then renamed over `path`.
+pub async fn write_atomic(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let temp_path = dir.join(format!(".{}.{}.tmp", file_name, Ulid::new()));
+    let permissions = fs::metadata(path).await.ok().map(|meta| meta.permissions());
+
+    let result = async {
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+            .await?;
+        file.write_all(bytes).await?;
+        file.sync_all().await?;
+        drop(file);
+
+        if let Some(permissions) = permissions {
+            fs::set_permissions(&temp_path, permissions).await?;
+        }
+        fs::rename(&temp_path, path).await?;
+        sync_dir(dir).await
+    }
+    .await;
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path).await;
+    }
+    result
+}
+
+/// Persists a rename by syncing the directory entry. Not supported outside unix.
+#[cfg(unix)]
+async fn sync_dir(dir: &Path) -> std::io::Result<()> {
+    fs::File::open(dir).await?.sync_all().await
+}
+
+#[cfg(not(unix))]
+async fn sync_dir(_dir: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Reads a capture and the text around it in its topic file. The text before it includes the
+/// file's preamble, so writing `before + capture + after` back keeps the file intact.
 pub async fn read_capture(
-    timestamp: &str,
-    file_name: &str,
-    subject: &str,
-) -> Result<
-    (
-        Vec<(String, Vec<String>)>,
-        (String, Vec<String>),
-        Vec<(String, Vec<String>)>,
-    ),
-    Error,
-> {
-    let capture_path = tool::source_path(file_name.to_string());
-    println!("{}", capture_path);
-    let capture_path_ref: &str = &capture_path;
-    let (is_file, path) = file_exists(capture_path_ref).await;
+    root: &Path,
+    header: &CaptureHeader,
+) -> Result<(String, CaptureSection, String), Error> {
+    let capture_path = tool::vault_path(root, &header.file_name())?;
+    let (is_file, path) = file_exists(capture_path).await;
 
     if is_file {
-        println!("{}{}", timestamp, subject);
-        let mut file_content: Vec<String> = Vec::new();
+        let mut file_content = String::new();
         let bytes = tokio::fs::read(&path)
             .await
             .map_err(|e| Error::IoError(e.kind()))?;
-        if let Ok(string) = String::from_utf8(bytes.clone()) {
-            file_content = string.lines().map(|s| s.to_string()).collect();
+        if let Ok(string) = String::from_utf8(bytes) {
+            file_content = string;
         } else {
             println!("Unable to convert bytes to string.");
         }
 
-        let prefix = "<!--yoink";
-        let delimiter = "::::";
-        let topic = &file_name[1..file_name.len() - 3];
-        let suffix = "-->";
-        let capture_string = format!(
-            "{}{}{}{}{}{}{}{}",
-            prefix, delimiter, timestamp, delimiter, topic, delimiter, subject, suffix
-        );
-        let mut before: Vec<(String, Vec<String>)> = Vec::new();
-        let mut content: (String, Vec<String>) = (String::new(), Vec::new());
-        let mut after: Vec<(String, Vec<String>)> = Vec::new();
-        let mut sections: Vec<(String, Vec<String>)> = Vec::new();
-        let mut current_section: Option<(String, Vec<String>)> = None;
-        for line in file_content.iter() {
-            if line.starts_with("<!--yoink") && line.ends_with("-->") {
-                if let Some((header, lines)) = current_section.take() {
-                    sections.push((header, lines));
-                }
+        TopicFile::parse(&file_content)
+            .split(header)
+            .ok_or(Error::CaptureNotFound)
+    } else {
+        eprintln!("Failed to read file. File does not exist.");
+        Err(Error::FileNotFound)
+    }
+}
 
-                current_section = Some((line.to_string(), Vec::new()));
-            } else if let Some((_, ref mut lines)) = current_section {
-                lines.push(line.to_string());
-            }
-        }
+/// Reads what comes before the first capture of a topic file.
+pub async fn read_preamble(root: PathBuf, file: String) -> Result<String, Error> {
+    Ok(TopicFile::parse(&read_text(&root, &file).await?).preamble)
+}
 
-        if let Some((header, lines)) = current_section {
-            sections.push((header, lines));
+/// Replaces what comes before the first capture of a topic file, leaving its captures as they
+/// are on disk now.
+pub async fn write_preamble(
+    root: PathBuf,
+    file: String,
+    preamble: String,
+) -> Result<PathBuf, Error> {
+    let mut topic = TopicFile::parse(&read_text(&root, &file).await?);
+    topic.set_preamble(&preamble);
+    write_file(root, file, topic.to_text()).await
+}
+
+/// Removes a capture section from its topic file, keeping a copy in the vault's trash.
+pub async fn delete_capture(root: PathBuf, capture: CaptureRecord) -> Result<TrashItem, Error> {
+    let file_name = capture.header.file_name();
+    let (before, content, after) = read_capture(&root, &capture.header).await?;
+    let trashed = trash::trash(&root, TrashKind::Capture, &file_name, content.to_text()).await?;
+
+    match write_file(root.clone(), file_name, before + &after).await {
+        Ok(path_buf) => {
+            println!("Updated: {}", path_buf.to_string_lossy());
+            Ok(trashed)
+        }
+        Err(e) => {
+            eprintln!("Failed to update file: {}", e);
+            trash::discard(&root, &trashed).await;
+            Err(e)
         }
+    }
+}
 
-        let mut found_content = false;
-        for (header, lines) in sections {
-            if header == capture_string {
-                content.0 = header;
-                content.1.extend(lines);
-                found_content = true;
-            } else if !found_content {
-                before.push((header, lines));
-            } else {
-                after.push((header, lines));
-            }
+/// Moves a capture section to the topic file of `topic`, rewriting the topic in its header.
+/// The destination is appended to first and put back if the source cannot be rewritten, so a
+/// failure never loses the capture or leaves it in both files.
+pub async fn move_capture(
+    root: PathBuf,
+    capture: CaptureRecord,
+    topic: String,
+) -> Result<CaptureRecord, Error> {
+    let topic = TopicName::parse(&topic)?;
+    let source_file = capture.header.file_name();
+    let (before, content, after) = read_capture(&root, &capture.header).await?;
+
+    let mut header = content.header.clone();
+    header.topic = topic.to_string();
+    let destination_file = header.file_name();
+    if destination_file == source_file {
+        return Ok(capture);
+    }
+
+    let mut moved = content.with_header(header);
+    if !moved.text.ends_with('\n') {
+        moved.text.push('\n');
+    }
+
+    let destination_path = tool::vault_path(&root, &destination_file)?;
+    let original = match fs::read_to_string(&destination_path).await {
+        Ok(content) => Some(content),
+        Err(e) if e.kind() == ErrorKind::NotFound => None,
+        Err(e) => {
+            eprintln!(
+                "Failed to read {}.\nUnderlying error: {}",
+                destination_path.display(),
+                e
+            );
+            return Err(Error::IoError(e.kind()));
         }
+    };
 
-        Ok((before, content, after))
-    } else {
-        eprintln!("Failed to read file. File does not exist.");
-        Err(Error::FileNotFound)
+    if let Err(e) = append_file(root.clone(), destination_file.clone(), moved.text.clone()).await {
+        restore_topic(&root, &destination_file, original).await;
+        return Err(e);
     }
+
+    if let Err(e) = write_file(root.clone(), source_file, before + &after).await {
+        restore_topic(&root, &destination_file, original).await;
+        return Err(e);
+    }
+
+    Ok(CaptureRecord::new(&moved, &destination_file))
 }
 
-pub async fn delete_capture(capture: Vec<String>) -> Result<bool, Error> {
-    match (capture.get(0), capture.get(1), capture.get(2)) {
-        (Some(timestamp), Some(file_path), Some(subject)) => {
-            let file_name = format!("_{}.md", file_path);
-            let capture_path = tool::source_path(file_name.to_string());
-            let capture_path_ref: &str = &capture_path;
-            let (is_file, path) = file_exists(capture_path_ref).await;
-
-            if is_file {
-                let mut file_content: Vec<String> = Vec::new();
-                let bytes = tokio::fs::read(&path)
-                    .await
-                    .map_err(|e| Error::IoError(e.kind()))?;
-                if let Ok(string) = String::from_utf8(bytes.clone()) {
-                    file_content = string.lines().map(|s| s.to_string()).collect();
-                } else {
-                    println!("Unable to convert bytes to string.");
+/// Puts a topic file back the way it was before a failed move, removing it if it was new.
+async fn restore_topic(root: &Path, file_name: &str, original: Option<String>) {
+    let result = match original {
+        Some(content) => write_file(root.to_path_buf(), file_name.to_string(), content)
+            .await
+            .map(|_| ()),
+        None => match tool::vault_path(root, file_name) {
+            Ok(path) => match fs::remove_file(path).await {
+                Ok(_) => {
+                    index::update_file(root, file_name).await;
+                    Ok(())
                 }
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+                Err(e) => Err(Error::IoError(e.kind())),
+            },
+            Err(e) => Err(e),
+        },
+    };
+    if let Err(e) = result {
+        eprintln!(
+            "Failed to roll back {}.\nUnderlying error: {}",
+            file_name, e
+        );
+    }
+}
 
-                let prefix = "<!--yoink";
-                let delimiter = "::::";
-                let suffix = "-->";
-                let capture_string = format!(
-                    "{}{}{}{}{}{}{}{}",
-                    prefix, delimiter, timestamp, delimiter, file_path, delimiter, subject, suffix
-                );
-                let mut before: Vec<(String, Vec<String>)> = Vec::new();
-                let mut content: (String, Vec<String>) = (String::new(), Vec::new());
-                let mut after: Vec<(String, Vec<String>)> = Vec::new();
-                let mut sections: Vec<(String, Vec<String>)> = Vec::new();
-                let mut current_section: Option<(String, Vec<String>)> = None;
-
-                for line in file_content.iter() {
-                    if line.starts_with("<!--yoink") && line.ends_with("-->") {
-                        if let Some((header, lines)) = current_section.take() {
-                            sections.push((header, lines));
-                        }
-
-                        current_section = Some((line.to_string(), Vec::new()));
-                    } else if let Some((_, ref mut lines)) = current_section {
-                        lines.push(line.to_string());
-                    }
-                }
+/// Renames `_from.md` to `_to.md` and rewrites the topic of every header in it. Refuses to
+/// replace an existing topic; merge into it instead. Returns the new file name.
+pub async fn rename_topic(root: PathBuf, from: String, to: String) -> Result<String, Error> {
+    let to = TopicName::parse(&to)?;
+    let source_file = format!("_{}.md", from);
+    let destination_file = to.file_name();
+    let destination_path = tool::vault_path(&root, &destination_file)?;
+    if file_exists(destination_path.clone()).await.0 {
+        eprintln!(
+            "Refusing to rename {}, {} exists.",
+            source_file, destination_file
+        );
+        return Err(Error::FileExists);
+    }
 
-                if let Some((header, lines)) = current_section {
-                    sections.push((header, lines));
-                }
+    let source_path = tool::vault_path(&root, &source_file)?;
+    let content = fs::read_to_string(&source_path)
+        .await
+        .map_err(|e| Error::IoError(e.kind()))?;
+    write_file(
+        root.clone(),
+        destination_file.clone(),
+        capture_record::retopic(&content, to.as_str()),
+    )
+    .await?;
 
-                let mut found_content = false;
-                for (header, lines) in sections {
-                    if header == capture_string {
-                        content.0 = header;
-                        content.1.extend(lines);
-                        found_content = true;
-                    } else if !found_content {
-                        before.push((header, lines));
-                    } else {
-                        after.push((header, lines));
-                    }
-                }
+    if let Err(e) = fs::remove_file(&source_path).await {
+        eprintln!(
+            "Failed to remove {}.\nUnderlying error: {}",
+            source_path.display(),
+            e
+        );
+        restore_topic(&root, &destination_file, None).await;
+        return Err(Error::IoError(e.kind()));
+    }
+    index::update_file(&root, &source_file).await;
 
-                let mut before_string = "".to_string();
-                let mut after_string = "".to_string();
-                let mut content_string = "".to_string();
-                for (header, lines) in before {
-                    before_string.push_str(&format!("{}\n", header.trim()));
-                    for line in lines {
-                        before_string.push_str(&format!("{}\n", line.trim()));
-                    }
-                }
-                for (header, lines) in after {
-                    after_string.push_str(&format!("{}\n", header.trim()));
-                    for line in lines {
-                        after_string.push_str(&format!("{}\n", line.trim()));
-                    }
-                }
-                content_string.push_str(&format!("{}\n", content.0.trim()));
-                for line in content.1 {
-                    content_string.push_str(&format!("{}\n", line.trim()));
-                }
+    Ok(destination_file)
+}
 
-                let update_content = format!("{}{}", before_string, after_string);
+/// Folds every capture of topic `from` into topic `into`, ordered by timestamp, then removes
+/// `_from.md`. Refuses two names for the same file. Returns the ID and subject collisions found
+/// on the way.
+pub async fn merge_topic(
+    root: PathBuf,
+    from: String,
+    into: String,
+    zone: AssumedZone,
+) -> Result<Vec<capture_record::Collision>, Error> {
+    let from = TopicName::parse(&from)?;
+    let into = TopicName::parse(&into)?;
+    let source_file = from.file_name();
+    let target_file = into.file_name();
+    let source_path = tool::vault_path(&root, &source_file)?;
+    let target_path = tool::vault_path(&root, &target_file)?;
+    if same_file(&source_path, &target_path).await {
+        eprintln!(
+            "Refusing to merge {} into {}, they are the same file.",
+            source_file, target_file
+        );
+        return Err(Error::SameFile);
+    }
 
-                match write_file(file_name, update_content).await {
-                    Ok(path_buf) => {
-                        println!("Updated: {}", path_buf.to_string_lossy());
-                    }
-                    Err(e) => {
-                        eprintln!("Failed to update file: {}", e);
-                    }
-                }
+    let incoming = fs::read_to_string(&source_path)
+        .await
+        .map_err(|e| Error::IoError(e.kind()))?;
+    let target = fs::read_to_string(&target_path)
+        .await
+        .map_err(|e| match e.kind() {
+            ErrorKind::NotFound => Error::FileNotFound,
+            kind => Error::IoError(kind),
+        })?;
 
-                Ok(true)
-            } else {
-                eprintln!("Failed to read file. File does not exist.");
-                Err(Error::FileNotFound)
-            }
+    let (merged, collisions) =
+        capture_record::merge_topics(&target, &incoming, into.as_str(), zone);
+    write_file(root.clone(), target_file.clone(), merged).await?;
+
+    if let Err(e) = fs::remove_file(&source_path).await {
+        eprintln!(
+            "Failed to remove {}.\nUnderlying error: {}",
+            source_path.display(),
+            e
+        );
+        restore_topic(&root, &target_file, Some(target)).await;
+        return Err(Error::IoError(e.kind()));
+    }
+    index::update_file(&root, &source_file).await;
+
+    Ok(collisions)
+}
+
+/// Whether two paths name the same file, also when a case-insensitive file system or a link
+/// gives it two names.
+async fn same_file(a: &Path, b: &Path) -> bool {
+    if let (Ok(a), Ok(b)) = (fs::canonicalize(a).await, fs::canonicalize(b).await) {
+        if a == b {
+            return true;
         }
-        _ => return Err(Error::IoError(ErrorKind::Other)),
     }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if let (Ok(a), Ok(b)) = (fs::metadata(a).await, fs::metadata(b).await) {
+            return a.dev() == b.dev() && a.ino() == b.ino();
+        }
+    }
+    false
 }
 
-async fn file_exists(current_path: &str) -> (bool, PathBuf) {
-    let path = PathBuf::from(current_path);
+async fn file_exists(path: PathBuf) -> (bool, PathBuf) {
     let check_path = path.clone();
 
     let file_exists = tokio::task::spawn_blocking(move || std::fs::metadata(&check_path).is_ok())
@@ -462,22 +684,12 @@ async fn file_exists(current_path: &str) -> (bool, PathBuf) {
     return (file_exists, path);
 }
 
-pub async fn capture_opened(capture: Vec<String>) -> Result<(String, PathBuf, String), Error> {
-    match (capture.get(0), capture.get(1), capture.get(2)) {
-        (Some(timestamp), Some(path), Some(subject)) => {
-            let capture_path = format!("_{}.md", path);
-            Ok((
-                timestamp.to_string(),
-                PathBuf::from(capture_path),
-                subject.to_string(),
-            ))
-        }
-        _ => return Err(Error::IoError(ErrorKind::NotFound)),
-    }
+pub async fn capture_opened(capture: CaptureRecord) -> Result<CaptureRecord, Error> {
+    Ok(capture)
 }
 
-pub async fn file_opened(file: String) -> Result<Vec<String>, Error> {
-    match read_file(&file).await {
+pub async fn file_opened(root: PathBuf, file: String) -> Result<String, Error> {
+    match read_text(&root, &file).await {
         Ok(contents) => Ok(contents),
         Err(e) => {
             eprintln!("Failure: file_opened");
@@ -486,12 +698,20 @@ pub async fn file_opened(file: String) -> Result<Vec<String>, Error> {
     }
 }
 
-pub async fn create_file(file: String) -> Result<bool, Error> {
-    let capture_file = format!("_{}.md", file);
-    let capture_path = tool::source_path(capture_file);
-    // TODO: update to avoid truncating existing files
-    match tokio::fs::File::create(&capture_path).await {
+/// Creates an empty topic file, refusing to touch one that already exists.
+pub async fn create_file(root: PathBuf, file: String) -> Result<bool, Error> {
+    let capture_path = tool::vault_path(&root, &TopicName::parse(&file)?.file_name())?;
+    let created = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&capture_path)
+        .await;
+    match created {
         Ok(_) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+            eprintln!("Not creating {}, it exists.", capture_path.display());
+            Err(Error::FileExists)
+        }
         Err(e) => {
             eprintln!("Failure: create_file\n{}", e);
             Err(Error::IoError(ErrorKind::Other))
@@ -499,23 +719,755 @@ pub async fn create_file(file: String) -> Result<bool, Error> {
     }
 }
 
-pub async fn delete_file(file: String) -> Result<bool, Error> {
-    let capture_path = tool::source_path(file);
-    match tokio::fs::remove_file(PathBuf::from(&capture_path)).await {
-        Ok(_) => Ok(true),
+/// Moves a whole topic file into the vault's trash.
+pub async fn delete_file(root: PathBuf, file: String) -> Result<TrashItem, Error> {
+    let capture_path = tool::vault_path(&root, &file)?;
+    let content = tokio::fs::read_to_string(&capture_path)
+        .await
+        .map_err(|e| {
+            eprintln!("Failure: delete_file\n{}", e);
+            Error::IoError(e.kind())
+        })?;
+    let trashed = trash::trash(&root, TrashKind::File, &file, &content).await?;
+
+    match tokio::fs::remove_file(&capture_path).await {
+        Ok(_) => {
+            index::update_file(&root, &file).await;
+            Ok(trashed)
+        }
         Err(e) => {
             eprintln!("Failure: delete_file\n{}", e);
+            trash::discard(&root, &trashed).await;
             Err(Error::IoError(ErrorKind::Other))
         }
     }
 }
 
+/// Rewrites every legacy timestamp of the vault as RFC 3339, reading it in `zone`. Everything
+/// else in the topic files is kept as is. Returns how many headers changed.
+pub async fn migrate_timestamps(root: PathBuf, zone: AssumedZone) -> Result<usize, Error> {
+    let mut migrated = 0;
+    for file in get_files(&root).await? {
+        let mut topic = TopicFile::parse(&read_text(&root, &file).await?);
+        let mut changed = 0;
+        for section in topic.sections.iter_mut() {
+            let mut header = section.header.clone();
+            if header.migrate_timestamp(zone) {
+                *section = section.with_header(header);
+                changed += 1;
+            }
+        }
+        if changed > 0 {
+            write_file(root.clone(), file, topic.to_text()).await?;
+            migrated += changed;
+        }
+    }
+    Ok(migrated)
+}
+
+/// Renames tag `from` to `to` in every listed topic file, merging it into `to` where a
+/// capture has both. Returns how many files changed.
+pub async fn rename_tag(
+    root: PathBuf,
+    files: Vec<String>,
+    from: String,
+    to: String,
+) -> Result<usize, Error> {
+    let mut changed = 0;
+    for file in files {
+        let path = tool::vault_path(&root, &file)?;
+        let content = fs::read_to_string(&path)
+            .await
+            .map_err(|e| Error::IoError(e.kind()))?;
+        let renamed = capture_record::rename_tag(&content, &from, &to);
+        if renamed != content {
+            write_file(root.clone(), file, renamed).await?;
+            changed += 1;
+        }
+    }
+    Ok(changed)
+}
+
 pub fn handle_hotkey(key: keyboard::Key, modifiers: keyboard::Modifiers) -> Option<Message> {
     match (key.as_ref(), modifiers) {
-        (keyboard::Key::Character(c), keyboard::Modifiers::ALT) if c == "e" => Some(Message::Edit),
-        (keyboard::Key::Character(c), keyboard::Modifiers::ALT) if c == "s" => {
-            Some(Message::UpdateCapture)
-        }
+        (keyboard::Key::Character("e"), keyboard::Modifiers::ALT) => Some(Message::Edit),
+        (keyboard::Key::Character("s"), keyboard::Modifiers::ALT) => Some(Message::UpdateCapture),
+        (keyboard::Key::Character("z"), keyboard::Modifiers::ALT) => Some(Message::Undo),
+        (keyboard::Key::Character("y"), keyboard::Modifiers::ALT) => Some(Message::Redo),
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WORK: &str =
+        "<!--yoink::::2025-04-01 09:00:00::::work::::standup::::id=01JQWORK0000000000000000001-->
+yesterday: shipped parser
+<!--yoink::::2025-04-02 09:00:00::::work::::retro::::id=01JQWORK0000000000000000002-->
+went well: tests
+";
+    const HOME: &str =
+        "<!--yoink::::2025-04-01 18:00:00::::home::::groceries::::id=01JQHOME0000000000000000001-->
+eggs
+milk
+<!--yoink::::2025-04-03 18:00:00::::home::::chores-->
+laundry
+";
+
+    async fn vault() -> PathBuf {
+        let root = std::env::temp_dir().join(format!("yyyoink-test-{}", CaptureHeader::new_id()));
+        tokio::fs::create_dir_all(&root).await.unwrap();
+        tokio::fs::write(root.join("_work.md"), WORK).await.unwrap();
+        tokio::fs::write(root.join("_home.md"), HOME).await.unwrap();
+        root
+    }
+
+    async fn find(root: &Path, subject: &str) -> CaptureRecord {
+        load_captures(root.to_path_buf())
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|record| record.header.subject == subject)
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn load_captures_records_each_topic_file() {
+        let root = vault().await;
+        let mut captures = load_captures(root.clone()).await.unwrap();
+        captures.sort_by(|a, b| a.header.subject.cmp(&b.header.subject));
+
+        let found: Vec<(&str, &str)> = captures
+            .iter()
+            .map(|record| (record.header.subject.as_str(), record.file_name.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("chores", "_home.md"),
+                ("groceries", "_home.md"),
+                ("retro", "_work.md"),
+                ("standup", "_work.md"),
+            ]
+        );
+        for record in &captures {
+            assert_eq!(record.header.file_name(), record.file_name);
+        }
+
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn read_capture_opens_from_its_own_topic_file() {
+        let root = vault().await;
+
+        let record = capture_opened(find(&root, "groceries").await)
+            .await
+            .unwrap();
+        let (before, content, after) = read_capture(&root, &record.header).await.unwrap();
+        assert!(before.is_empty());
+        assert_eq!(content.body(), "eggs\nmilk\n");
+        assert!(after.starts_with("<!--yoink::::2025-04-03 18:00:00::::home::::chores-->"));
+
+        let record = capture_opened(find(&root, "retro").await).await.unwrap();
+        let (before, content, after) = read_capture(&root, &record.header).await.unwrap();
+        assert!(before.contains("::::standup::::"));
+        assert_eq!(content.body(), "went well: tests\n");
+        assert!(after.is_empty());
+
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn read_capture_without_id_falls_back_to_full_header() {
+        let root = vault().await;
+
+        let record = find(&root, "chores").await;
+        assert!(record.header.id.is_none());
+        let (_, content, _) = read_capture(&root, &record.header).await.unwrap();
+        assert_eq!(content.body(), "laundry\n");
+
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn saving_a_capture_only_touches_its_topic_file() {
+        let root = vault().await;
+
+        let record = find(&root, "standup").await;
+        let (before, _, after) = read_capture(&root, &record.header).await.unwrap();
+        let content = format!(
+            "{}{}\nyesterday: shipped tests\n{}",
+            before, record.header, after
+        );
+        write_file(root.clone(), record.header.file_name(), content)
+            .await
+            .unwrap();
+
+        let (_, content, _) = read_capture(&root, &record.header).await.unwrap();
+        assert_eq!(content.body(), "yesterday: shipped tests\n");
+        let home = tokio::fs::read_to_string(root.join("_home.md"))
+            .await
+            .unwrap();
+        assert_eq!(home, HOME);
+
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn merge_topic_refuses_two_names_for_the_same_file() {
+        let root = vault().await;
+        assert!(matches!(
+            merge_topic(
+                root.clone(),
+                "work".into(),
+                " work ".into(),
+                AssumedZone::Local
+            )
+            .await,
+            Err(Error::SameFile)
+        ));
+        assert!(matches!(
+            merge_topic(
+                root.clone(),
+                "../work".into(),
+                "home".into(),
+                AssumedZone::Local
+            )
+            .await,
+            Err(Error::InvalidTopic(_))
+        ));
+
+        #[cfg(unix)]
+        {
+            std::fs::hard_link(root.join("_work.md"), root.join("_Work.md")).unwrap();
+            assert!(matches!(
+                merge_topic(
+                    root.clone(),
+                    "work".into(),
+                    "Work".into(),
+                    AssumedZone::Local
+                )
+                .await,
+                Err(Error::SameFile)
+            ));
+            std::os::unix::fs::symlink(root.join("_work.md"), root.join("_WORK.md")).unwrap();
+            assert!(matches!(
+                merge_topic(
+                    root.clone(),
+                    "WORK".into(),
+                    "work".into(),
+                    AssumedZone::Local
+                )
+                .await,
+                Err(Error::SameFile)
+            ));
+        }
+        let work = tokio::fs::read_to_string(root.join("_work.md"))
+            .await
+            .unwrap();
+        assert_eq!(work, WORK);
+
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn delete_capture_removes_only_from_its_topic_file() {
+        let root = vault().await;
+
+        let record = find(&root, "groceries").await;
+        delete_capture(root.clone(), record.clone()).await.unwrap();
+
+        let captures = load_captures(root.clone()).await.unwrap();
+        assert_eq!(captures.len(), 3);
+        assert!(!captures
+            .iter()
+            .any(|r| r.header.is_same_capture(&record.header)));
+        let work = tokio::fs::read_to_string(root.join("_work.md"))
+            .await
+            .unwrap();
+        assert_eq!(work, WORK);
+
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn write_file_replaces_atomically_and_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = vault().await;
+        let path = root.join("_work.md");
+        tokio::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))
+            .await
+            .unwrap();
+
+        write_file(
+            root.clone(),
+            "_work.md".to_string(),
+            "rewritten\n".to_string(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            tokio::fs::read_to_string(&path).await.unwrap(),
+            "rewritten\n"
+        );
+        let mode = tokio::fs::metadata(&path)
+            .await
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let mut entries = tokio::fs::read_dir(&root).await.unwrap();
+        while let Some(entry) = entries.next_entry().await.unwrap() {
+            assert!(!entry.file_name().to_string_lossy().ends_with(".tmp"));
+        }
+
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn delete_capture_keeps_indentation_of_other_captures() {
+        let root = vault().await;
+        let kept =
+            "<!--yoink::::2025-04-05 09:00:00::::code::::kept::::id=01JQCODE000000000000000001-->
+```python
+def f():
+\treturn [
+\t\t1,
+\t]
+```
+\t- item
+\t\t- nested
+";
+        let dropped = "<!--yoink::::2025-04-06 09:00:00::::code::::dropped::::id=01JQCODE000000000000000002-->
+    gone
+";
+        tokio::fs::write(
+            root.join("_code.md"),
+            format!("{}{}{}", kept, dropped, kept.replace("0001", "0003")),
+        )
+        .await
+        .unwrap();
+
+        delete_capture(root.clone(), find(&root, "dropped").await)
+            .await
+            .unwrap();
+
+        let code = tokio::fs::read_to_string(root.join("_code.md"))
+            .await
+            .unwrap();
+        assert_eq!(code, format!("{}{}", kept, kept.replace("0001", "0003")));
+
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn rename_tag_rewrites_only_files_that_use_it() {
+        let root = vault().await;
+        tokio::fs::write(root.join("_work.md"), WORK.replace("tests", "#testing"))
+            .await
+            .unwrap();
+        let files = vec!["_work.md".to_string(), "_home.md".to_string()];
+
+        let changed = rename_tag(root.clone(), files, "testing".into(), "qa".into())
+            .await
+            .unwrap();
+        assert_eq!(changed, 1);
+        assert_eq!(find(&root, "retro").await.tags, ["qa"]);
+        let home = tokio::fs::read_to_string(root.join("_home.md"))
+            .await
+            .unwrap();
+        assert_eq!(home, HOME);
+
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn move_capture_refiles_the_section_with_its_new_topic() {
+        let root = vault().await;
+        let moved = move_capture(root.clone(), find(&root, "retro").await, "home".into())
+            .await
+            .unwrap();
+        assert_eq!(moved.header.topic, "home");
+        assert_eq!(moved.file_name, "_home.md");
+        assert_eq!(
+            moved.header.id.as_deref(),
+            Some("01JQWORK0000000000000000002")
+        );
+
+        let work = tokio::fs::read_to_string(root.join("_work.md"))
+            .await
+            .unwrap();
+        let home = tokio::fs::read_to_string(root.join("_home.md"))
+            .await
+            .unwrap();
+        assert_eq!(work, WORK.split("<!--yoink::::2025-04-02").next().unwrap());
+        assert_eq!(
+            home,
+            format!(
+                "{}\n<!--yoink::::2025-04-02 09:00:00::::home::::retro::::id=01JQWORK0000000000000000002-->\nwent well: tests\n",
+                HOME
+            )
+        );
+
+        // Moving into a brand new topic creates its file.
+        move_capture(root.clone(), moved, "archive".into())
+            .await
+            .unwrap();
+        assert_eq!(find(&root, "retro").await.file_name, "_archive.md");
+
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_move_leaves_both_files_untouched() {
+        let root = vault().await;
+        let retro = find(&root, "retro").await;
+        // A directory where the destination file should be makes the append fail.
+        tokio::fs::create_dir(root.join("_broken.md"))
+            .await
+            .unwrap();
+
+        let result = move_capture(root.clone(), retro, "broken".into()).await;
+        assert!(result.is_err());
+        let work = tokio::fs::read_to_string(root.join("_work.md"))
+            .await
+            .unwrap();
+        assert_eq!(work, WORK);
+        assert!(root.join("_broken.md").is_dir());
+
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    /// Writes a topic whose file name is too long for `write_atomic`'s temporary file next to
+    /// it, so rewriting it fails even for root, after a move has already written its destination.
+    async fn unwritable_source(root: &Path) -> CaptureRecord {
+        let topic = "t".repeat(230);
+        tokio::fs::write(
+            root.join(format!("_{}.md", topic)),
+            format!(
+                "<!--yoink::::2025-04-04 09:00:00::::{}::::stuck::::id=01JQSTUCK00000000000000001-->\nkeep me\n",
+                topic
+            ),
+        )
+        .await
+        .unwrap();
+        find(root, "stuck").await
+    }
+
+    #[tokio::test]
+    async fn failed_source_rewrite_restores_an_existing_destination() {
+        let root = vault().await;
+        let stuck = unwritable_source(&root).await;
+        let source = tool::source_path(&root, stuck.file_name.clone());
+        let original = tokio::fs::read_to_string(&source).await.unwrap();
+
+        let result = move_capture(root.clone(), stuck, "home".into()).await;
+        assert!(result.is_err());
+        let home = tokio::fs::read_to_string(root.join("_home.md"))
+            .await
+            .unwrap();
+        assert_eq!(home, HOME);
+        assert_eq!(tokio::fs::read_to_string(&source).await.unwrap(), original);
+
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_source_rewrite_removes_a_new_destination() {
+        let root = vault().await;
+        let stuck = unwritable_source(&root).await;
+        let source = tool::source_path(&root, stuck.file_name.clone());
+        let original = tokio::fs::read_to_string(&source).await.unwrap();
+
+        let result = move_capture(root.clone(), stuck, "fresh".into()).await;
+        assert!(result.is_err());
+        assert!(!root.join("_fresh.md").exists());
+        assert_eq!(tokio::fs::read_to_string(&source).await.unwrap(), original);
+
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn rename_topic_moves_the_file_and_its_headers() {
+        let root = vault().await;
+        assert!(matches!(
+            rename_topic(root.clone(), "work".into(), "home".into()).await,
+            Err(Error::FileExists)
+        ));
+
+        let renamed = rename_topic(root.clone(), "work".into(), "job".into())
+            .await
+            .unwrap();
+        assert_eq!(renamed, "_job.md");
+        assert!(!root.join("_work.md").exists());
+        let job = tokio::fs::read_to_string(root.join("_job.md"))
+            .await
+            .unwrap();
+        assert_eq!(job, WORK.replace("::::work::::", "::::job::::"));
+
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn merge_topic_folds_captures_and_removes_the_source() {
+        let root = vault().await;
+        let collisions = merge_topic(
+            root.clone(),
+            "work".into(),
+            "home".into(),
+            AssumedZone::Local,
+        )
+        .await
+        .unwrap();
+        assert!(collisions.is_empty());
+        assert!(!root.join("_work.md").exists());
+
+        let captures = load_captures(root.clone()).await.unwrap();
+        let subjects: Vec<&str> = captures
+            .iter()
+            .map(|record| record.header.subject.as_str())
+            .collect();
+        assert_eq!(subjects, ["standup", "groceries", "retro", "chores"]);
+        assert!(captures.iter().all(|record| record.file_name == "_home.md"));
+
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn read_topics_reindexes_changed_and_removed_files() {
+        let root = vault().await;
+        tokio::fs::remove_file(root.join("_home.md")).await.unwrap();
+
+        let topics = read_topics(root.clone(), vec!["_work.md".into(), "_home.md".into()])
+            .await
+            .unwrap();
+        assert_eq!(topics[0], ("_work.md".to_string(), Some(WORK.to_string())));
+        assert_eq!(topics[1], ("_home.md".to_string(), None));
+
+        let records = capture_records("_work.md", WORK);
+        let subjects: Vec<&str> = records.iter().map(|r| r.header.subject.as_str()).collect();
+        assert_eq!(subjects, ["standup", "retro"]);
+        assert!(records.iter().all(|r| r.file_name == "_work.md"));
+
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn whole_file_keeps_its_line_endings() {
+        let root = vault().await;
+        let crlf = WORK.replace('\n', "\r\n");
+        let crlf = crlf.trim_end();
+        tokio::fs::write(root.join("_work.md"), crlf).await.unwrap();
+
+        let content = file_opened(root.clone(), "_work.md".to_string())
+            .await
+            .unwrap();
+        assert_eq!(content, crlf);
+        let endings = LineEndings::detect(&content);
+        assert_eq!(
+            endings,
+            LineEndings {
+                crlf: true,
+                final_newline: false,
+            }
+        );
+
+        // The editor hands back `\n` line breaks and always a final one.
+        let edited = format!(
+            "{}\n",
+            content.replace("\r\n", "\n").replace("tests", "docs")
+        );
+        write_file(
+            root.clone(),
+            "_work.md".to_string(),
+            endings.restore(&edited),
+        )
+        .await
+        .unwrap();
+        let saved = tokio::fs::read_to_string(root.join("_work.md"))
+            .await
+            .unwrap();
+        assert_eq!(saved, crlf.replace("tests", "docs"));
+
+        let lf = LineEndings::detect(HOME);
+        assert_eq!(lf, LineEndings::default());
+        assert_eq!(lf.restore(HOME), HOME);
+
+        tokio::fs::remove_dir_all(root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn refuses_paths_outside_the_vault() {
+        let root = vault().await;
+        let outside = root.with_extension("outside.md");
+        tokio::fs::write(&outside, "keep me").await.unwrap();
+        let escape = format!("../{}", outside.file_name().unwrap().to_string_lossy());
+
+        assert!(matches!(
+            create_file(root.clone(), "../../.bashrc".to_string()).await,
+            Err(Error::InvalidTopic(_))
+        ));
+        assert!(matches!(
+            write_file(root.clone(), escape.clone(), "gone".to_string()).await,
+            Err(Error::OutsideVault)
+        ));
+        assert!(matches!(
+            delete_file(root.clone(), escape.clone()).await,
+            Err(Error::OutsideVault)
+        ));
+        assert!(matches!(
+            delete_file(root.clone(), outside.to_string_lossy().to_string()).await,
+            Err(Error::OutsideVault)
+        ));
+        assert!(matches!(
+            write_file(
+                root.clone(),
+                format!(".trash/{}", escape),
+                "gone".to_string()
+            )
+            .await,
+            Err(Error::OutsideVault)
+        ));
+        assert!(matches!(
+            rename_topic(root.clone(), "work".to_string(), "a/b".to_string()).await,
+            Err(Error::InvalidTopic(_))
+        ));
+        let standup = find(&root, "standup").await;
+        assert!(matches!(
+            move_capture(root.clone(), standup, "../home".to_string()).await,
+            Err(Error::InvalidTopic(_))
+        ));
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&outside, root.join("_linked.md")).unwrap();
+            assert!(matches!(
+                write_file(root.clone(), "_linked.md".to_string(), "gone".to_string()).await,
+                Err(Error::OutsideVault)
+            ));
+        }
+
+        assert_eq!(
+            tokio::fs::read_to_string(&outside).await.unwrap(),
+            "keep me"
+        );
+        assert!(fs::metadata(root.join("_work.md")).await.is_ok());
+        tokio::fs::remove_file(outside).await.unwrap();
+        tokio::fs::remove_dir_all(root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn create_file_never_truncates_an_existing_topic() {
+        let root = vault().await;
+
+        assert!(matches!(
+            create_file(root.clone(), "work".to_string()).await,
+            Err(Error::FileExists)
+        ));
+        assert_eq!(
+            tokio::fs::read_to_string(root.join("_work.md"))
+                .await
+                .unwrap(),
+            WORK
+        );
+
+        assert!(create_file(root.clone(), "fresh".to_string())
+            .await
+            .unwrap());
+        assert_eq!(
+            tokio::fs::read_to_string(root.join("_fresh.md"))
+                .await
+                .unwrap(),
+            ""
+        );
+
+        tokio::fs::remove_dir_all(root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn rewrites_keep_the_preamble() {
+        let root = vault().await;
+        let preamble = "# Work log\n\nfront matter, not a capture\n";
+        tokio::fs::write(root.join("_work.md"), format!("{}{}", preamble, WORK))
+            .await
+            .unwrap();
+
+        let standup = find(&root, "standup").await;
+        let (before, _, _) = read_capture(&root, &standup.header).await.unwrap();
+        assert_eq!(before, preamble);
+        delete_capture(root.clone(), standup).await.unwrap();
+        let retro = find(&root, "retro").await;
+        move_capture(root.clone(), retro, "home".to_string())
+            .await
+            .unwrap();
+        assert_eq!(
+            tokio::fs::read_to_string(root.join("_work.md"))
+                .await
+                .unwrap(),
+            preamble
+        );
+
+        tokio::fs::write(root.join("_work.md"), format!("{}{}", preamble, WORK))
+            .await
+            .unwrap();
+        assert_eq!(
+            read_preamble(root.clone(), "_work.md".to_string())
+                .await
+                .unwrap(),
+            preamble
+        );
+        write_preamble(
+            root.clone(),
+            "_work.md".to_string(),
+            "# Renamed".to_string(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            tokio::fs::read_to_string(root.join("_work.md"))
+                .await
+                .unwrap(),
+            format!("# Renamed\n{}", WORK)
+        );
+
+        tokio::fs::remove_dir_all(root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn migrates_legacy_timestamps_in_place() {
+        let root = vault().await;
+        let preamble = "# Work log\r\n";
+        let work = format!(
+            "{}{}<!--yoink::::2025-04-03T09:00:00-04:00::::work::::planning-->\r\nalready migrated\r\n",
+            preamble,
+            WORK.replace('\n', "\r\n")
+        );
+        tokio::fs::write(root.join("_work.md"), &work)
+            .await
+            .unwrap();
+
+        let zone = AssumedZone::parse("+02:00").unwrap();
+        assert_eq!(migrate_timestamps(root.clone(), zone).await.unwrap(), 4);
+        let migrated = tokio::fs::read_to_string(root.join("_work.md"))
+            .await
+            .unwrap();
+        assert_eq!(
+            migrated,
+            work.replace("2025-04-01 09:00:00", "2025-04-01T09:00:00+02:00")
+                .replace("2025-04-02 09:00:00", "2025-04-02T09:00:00+02:00")
+        );
+        let home = tokio::fs::read_to_string(root.join("_home.md"))
+            .await
+            .unwrap();
+        assert!(home.contains("::::2025-04-03T18:00:00+02:00::::home::::chores-->"));
+        assert_eq!(migrate_timestamps(root.clone(), zone).await.unwrap(), 0);
+
+        tokio::fs::remove_dir_all(root).await.unwrap();
+    }
+}
@@ -0,0 +1,516 @@
+use crate::capture::capture_record::{self, CaptureHeader};
+use iced::advanced::text::highlighter::{self, Format};
+use iced::{font, Color, Font, Theme};
+use std::ops::Range;
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+    "unsafe", "use", "where", "while",
+];
+const PYTHON_KEYWORDS: &[&str] = &[
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class", "continue",
+    "def", "del", "elif", "else", "except", "finally", "for", "from", "global", "if", "import",
+    "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while",
+    "with", "yield",
+];
+const SHELL_KEYWORDS: &[&str] = &[
+    "case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function", "if", "in",
+    "local", "readonly", "return", "select", "then", "until", "while",
+];
+const JSON_KEYWORDS: &[&str] = &["true", "false", "null"];
+const YAML_KEYWORDS: &[&str] = &["true", "false", "null", "yes", "no", "on", "off"];
+
+/// A language inside a fenced code block, taken from the fence's info string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    Rust,
+    Python,
+    Shell,
+    Json,
+    Yaml,
+    Other,
+}
+
+impl Language {
+    pub fn from_info(info: &str) -> Self {
+        match info
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_lowercase()
+            .as_str()
+        {
+            "rust" | "rs" => Language::Rust,
+            "python" | "py" => Language::Python,
+            "sh" | "bash" | "shell" | "zsh" | "console" => Language::Shell,
+            "json" | "jsonc" => Language::Json,
+            "yaml" | "yml" => Language::Yaml,
+            _ => Language::Other,
+        }
+    }
+
+    fn keywords(self) -> &'static [&'static str] {
+        match self {
+            Language::Rust => RUST_KEYWORDS,
+            Language::Python => PYTHON_KEYWORDS,
+            Language::Shell => SHELL_KEYWORDS,
+            Language::Json => JSON_KEYWORDS,
+            Language::Yaml => YAML_KEYWORDS,
+            Language::Other => &[],
+        }
+    }
+}
+
+/// Where a line starts: in markdown text or inside a fenced code block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineState {
+    Markdown,
+    Code(Language),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Highlight {
+    Header,
+    BrokenHeader,
+    Heading,
+    Quote,
+    Marker,
+    Strong,
+    Emphasis,
+    Code,
+    Link,
+    Tag,
+    Keyword,
+    String,
+    Comment,
+    Number,
+    Key,
+}
+
+/// Highlights markdown, the code in fenced blocks and capture headers, one line at a time.
+/// The state at the start of every line is kept so an edit re-highlights from the changed line.
+pub struct Highlighter {
+    states: Vec<LineState>,
+    current_line: usize,
+}
+
+impl highlighter::Highlighter for Highlighter {
+    type Settings = ();
+    type Highlight = Highlight;
+    type Iterator<'a> = std::vec::IntoIter<(Range<usize>, Highlight)>;
+
+    fn new(_settings: &Self::Settings) -> Self {
+        Self {
+            states: vec![LineState::Markdown],
+            current_line: 0,
+        }
+    }
+
+    fn update(&mut self, _new_settings: &Self::Settings) {
+        self.change_line(0);
+    }
+
+    fn change_line(&mut self, line: usize) {
+        self.current_line = line.min(self.states.len() - 1);
+        self.states.truncate(self.current_line + 1);
+    }
+
+    fn highlight_line(&mut self, line: &str) -> Self::Iterator<'_> {
+        let state = self.states[self.current_line];
+        let (highlights, next) = highlight_line(state, line);
+        self.states.truncate(self.current_line + 1);
+        self.states.push(next);
+        self.current_line += 1;
+        highlights.into_iter()
+    }
+
+    fn current_line(&self) -> usize {
+        self.current_line
+    }
+}
+
+/// How each highlight is drawn. Capture headers are dimmed so they read as markup, not notes,
+/// and broken ones are flagged in red.
+pub fn format(highlight: &Highlight, _theme: &Theme) -> Format<Font> {
+    let (r, g, b) = match highlight {
+        Highlight::Header => (120, 100, 90),
+        Highlight::BrokenHeader => (255, 90, 90),
+        Highlight::Heading | Highlight::Link => (255, 160, 120),
+        Highlight::Quote => (205, 180, 150),
+        Highlight::Marker => (180, 60, 60),
+        Highlight::Strong | Highlight::Emphasis => (255, 224, 181),
+        Highlight::Code | Highlight::String => (170, 200, 120),
+        Highlight::Tag => (255, 120, 90),
+        Highlight::Keyword => (255, 140, 100),
+        Highlight::Comment => (130, 115, 105),
+        Highlight::Number => (200, 160, 255),
+        Highlight::Key => (120, 190, 230),
+    };
+    let font = match highlight {
+        Highlight::Heading | Highlight::Strong | Highlight::Keyword => Some(Font {
+            weight: font::Weight::Bold,
+            ..Font::DEFAULT
+        }),
+        Highlight::Header | Highlight::Emphasis | Highlight::Quote | Highlight::Comment => {
+            Some(Font {
+                style: font::Style::Italic,
+                ..Font::DEFAULT
+            })
+        }
+        _ => None,
+    };
+    Format {
+        color: Some(Color::from_rgb8(r, g, b)),
+        font,
+    }
+}
+
+/// The info string of a fence line, or `None` if the line is not a fence.
+fn fence(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    line.strip_prefix("```")
+        .or_else(|| line.strip_prefix("~~~"))
+        .map(|info| info.trim_start_matches(['`', '~']))
+}
+
+/// Highlights one line starting in `state` and returns the state the next line starts in.
+pub fn highlight_line(state: LineState, line: &str) -> (Vec<(Range<usize>, Highlight)>, LineState) {
+    let text = line.trim_end_matches(['\n', '\r']);
+    if CaptureHeader::parse(text).is_some() {
+        return (
+            vec![(0..text.len(), Highlight::Header)],
+            LineState::Markdown,
+        );
+    }
+    if capture_record::looks_like_header(text) {
+        return (vec![(0..text.len(), Highlight::BrokenHeader)], state);
+    }
+
+    match state {
+        LineState::Code(language) => match fence(text) {
+            Some(_) => (
+                vec![(0..text.len(), Highlight::Marker)],
+                LineState::Markdown,
+            ),
+            None => (code_line(language, text), state),
+        },
+        LineState::Markdown => match fence(text) {
+            Some(info) => (
+                vec![(0..text.len(), Highlight::Marker)],
+                LineState::Code(Language::from_info(info)),
+            ),
+            None => (markdown_line(text), LineState::Markdown),
+        },
+    }
+}
+
+fn markdown_line(line: &str) -> Vec<(Range<usize>, Highlight)> {
+    let trimmed = line.trim_start();
+    let indent = line.len() - trimmed.len();
+
+    let hashes = trimmed.bytes().take_while(|&b| b == b'#').count();
+    if (1..=6).contains(&hashes) && trimmed[hashes..].chars().next().is_none_or(|c| c == ' ') {
+        return vec![(0..line.len(), Highlight::Heading)];
+    }
+    if trimmed.starts_with('>') {
+        return vec![(0..line.len(), Highlight::Quote)];
+    }
+    let compact: String = trimmed.chars().filter(|c| !c.is_whitespace()).collect();
+    if compact.len() >= 3
+        && ["-", "*", "_"]
+            .iter()
+            .any(|rule| compact.chars().all(|c| c.to_string() == *rule))
+    {
+        return vec![(0..line.len(), Highlight::Marker)];
+    }
+
+    let mut highlights = Vec::new();
+    let digits = trimmed.bytes().take_while(u8::is_ascii_digit).count();
+    let marker = if ["- ", "* ", "+ "].iter().any(|m| trimmed.starts_with(m)) {
+        1
+    } else if digits > 0
+        && (trimmed[digits..].starts_with(". ") || trimmed[digits..].starts_with(") "))
+    {
+        digits + 1
+    } else {
+        0
+    };
+    if marker > 0 {
+        highlights.push((indent..indent + marker, Highlight::Marker));
+    }
+
+    inline(line, indent + marker, &mut highlights);
+    highlights
+}
+
+/// Finds `needle` in `line` at or after `from`.
+fn find_from(line: &str, from: usize, needle: &str) -> Option<usize> {
+    line.get(from..)?.find(needle).map(|i| i + from)
+}
+
+fn inline(line: &str, from: usize, highlights: &mut Vec<(Range<usize>, Highlight)>) {
+    let bytes = line.as_bytes();
+    let tags = capture_record::tag_spans(line);
+    let mut i = from;
+
+    while i < bytes.len() {
+        let next = bytes.get(i + 1).copied();
+        let after_word = i > 0 && bytes[i - 1].is_ascii_alphanumeric();
+        let found = match bytes[i] {
+            b'`' => find_from(line, i + 1, "`").map(|end| (end + 1, Highlight::Code)),
+            b'*' | b'_' if next == Some(bytes[i]) => {
+                let delimiter = &line[i..i + 2];
+                find_from(line, i + 2, delimiter).map(|end| (end + 2, Highlight::Strong))
+            }
+            b'*' | b'_'
+                if next.is_some_and(|c| !c.is_ascii_whitespace())
+                    && !(bytes[i] == b'_' && after_word) =>
+            {
+                let delimiter = &line[i..i + 1];
+                find_from(line, i + 1, delimiter).map(|end| (end + 1, Highlight::Emphasis))
+            }
+            b'[' => find_from(line, i + 1, "](")
+                .and_then(|middle| find_from(line, middle + 2, ")"))
+                .map(|end| (end + 1, Highlight::Link)),
+            b'#' => tags
+                .iter()
+                .find(|(start, _)| *start == i)
+                .map(|&(_, end)| (end, Highlight::Tag)),
+            _ => None,
+        };
+
+        match found {
+            Some((end, highlight)) => {
+                highlights.push((i..end, highlight));
+                i = end;
+            }
+            None => i += 1,
+        }
+    }
+}
+
+fn is_word(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_'
+}
+
+fn code_line(language: Language, line: &str) -> Vec<(Range<usize>, Highlight)> {
+    let bytes = line.as_bytes();
+    let mut highlights = Vec::new();
+    let mut i = 0;
+
+    if language == Language::Yaml {
+        let trimmed = line.trim_start();
+        let start = line.len() - trimmed.len() + if trimmed.starts_with("- ") { 2 } else { 0 };
+        if let Some(colon) = find_from(line, start, ":") {
+            let key = &line[start..colon];
+            if !key.is_empty()
+                && !key.contains(['"', '\'', '#'])
+                && line[colon + 1..]
+                    .chars()
+                    .next()
+                    .is_none_or(char::is_whitespace)
+            {
+                highlights.push((start..colon, Highlight::Key));
+                i = colon + 1;
+            }
+        }
+    }
+
+    while i < bytes.len() {
+        let byte = bytes[i];
+        let after_space = i == 0 || bytes[i - 1].is_ascii_whitespace();
+        let comment = match language {
+            Language::Rust => line[i..].starts_with("//"),
+            Language::Python => byte == b'#',
+            Language::Shell | Language::Yaml => byte == b'#' && after_space,
+            Language::Json | Language::Other => false,
+        };
+        if comment {
+            highlights.push((i..line.len(), Highlight::Comment));
+            break;
+        }
+
+        let quoted = match byte {
+            b'"' => language != Language::Other,
+            b'\'' => match language {
+                Language::Python | Language::Shell | Language::Yaml => true,
+                // A char literal, not a lifetime.
+                Language::Rust => {
+                    bytes.get(i + 2) == Some(&b'\'') || bytes.get(i + 1) == Some(&b'\\')
+                }
+                _ => false,
+            },
+            _ => false,
+        };
+        if quoted {
+            let mut end = i + 1;
+            while end < bytes.len() && bytes[end] != byte {
+                end += if bytes[end] == b'\\' { 2 } else { 1 };
+            }
+            let end = (end + 1).min(bytes.len());
+            let is_key = language == Language::Json && line[end..].trim_start().starts_with(':');
+            highlights.push((
+                i..end,
+                if is_key {
+                    Highlight::Key
+                } else {
+                    Highlight::String
+                },
+            ));
+            i = end;
+            continue;
+        }
+
+        if language == Language::Shell && byte == b'$' {
+            let end = if bytes.get(i + 1) == Some(&b'{') {
+                find_from(line, i, "}").map_or(line.len(), |end| end + 1)
+            } else {
+                i + 1 + bytes[i + 1..].iter().take_while(|&&b| is_word(b)).count()
+            };
+            if end > i + 1 {
+                highlights.push((i..end, Highlight::Key));
+                i = end;
+                continue;
+            }
+        }
+
+        if is_word(byte) && (i == 0 || !is_word(bytes[i - 1])) {
+            let len = bytes[i..]
+                .iter()
+                .take_while(|&&b| is_word(b) || (byte.is_ascii_digit() && b == b'.'))
+                .count();
+            let word = &line[i..i + len];
+            if byte.is_ascii_digit() || (byte == b'-' && len > 1) {
+                highlights.push((i..i + len, Highlight::Number));
+            } else if language.keywords().contains(&word) {
+                highlights.push((i..i + len, Highlight::Keyword));
+            }
+            i += len;
+            continue;
+        }
+
+        i += 1;
+    }
+
+    highlights
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use iced::advanced::text::highlighter::Highlighter as _;
+
+    fn spans(state: LineState, line: &str) -> Vec<(&str, Highlight)> {
+        highlight_line(state, line)
+            .0
+            .into_iter()
+            .map(|(range, highlight)| (&line[range], highlight))
+            .collect()
+    }
+
+    #[test]
+    fn highlights_markdown_structure() {
+        let md = LineState::Markdown;
+        assert_eq!(spans(md, "## Plan"), [("## Plan", Highlight::Heading)]);
+        assert_eq!(spans(md, "#tag at start"), [("#tag", Highlight::Tag)]);
+        assert_eq!(
+            spans(md, "- **bold** and *soft* `snake_case` [docs](https://x.y)"),
+            [
+                ("-", Highlight::Marker),
+                ("**bold**", Highlight::Strong),
+                ("*soft*", Highlight::Emphasis),
+                ("`snake_case`", Highlight::Code),
+                ("[docs](https://x.y)", Highlight::Link),
+            ]
+        );
+        assert_eq!(spans(md, "keep snake_case_names plain"), []);
+        assert_eq!(
+            spans(
+                md,
+                "<!--yoink::::2025-04-01 09:00:00::::dev::::plan::::id=01JQDEV00000000000000000001-->"
+            )[0]
+            .1,
+            Highlight::Header
+        );
+        assert_eq!(
+            spans(md, "<!--yoink::::2025-04-01 09:00:00::::dev-->"),
+            [(
+                "<!--yoink::::2025-04-01 09:00:00::::dev-->",
+                Highlight::BrokenHeader
+            )]
+        );
+    }
+
+    #[test]
+    fn highlights_fenced_code_by_language() {
+        let (_, state) = highlight_line(LineState::Markdown, "```rust");
+        assert_eq!(state, LineState::Code(Language::Rust));
+        assert_eq!(
+            spans(state, "let s = \"hi\"; // done"),
+            [
+                ("let", Highlight::Keyword),
+                ("\"hi\"", Highlight::String),
+                ("// done", Highlight::Comment),
+            ]
+        );
+        assert_eq!(highlight_line(state, "```").1, LineState::Markdown);
+
+        let python = LineState::Code(Language::Python);
+        assert_eq!(
+            spans(python, "def f(x): return 42  # answer"),
+            [
+                ("def", Highlight::Keyword),
+                ("return", Highlight::Keyword),
+                ("42", Highlight::Number),
+                ("# answer", Highlight::Comment),
+            ]
+        );
+        assert_eq!(
+            spans(
+                LineState::Code(Language::Shell),
+                "echo \"$HOME\" ${USER} # hi"
+            ),
+            [
+                ("\"$HOME\"", Highlight::String),
+                ("${USER}", Highlight::Key),
+                ("# hi", Highlight::Comment),
+            ]
+        );
+        assert_eq!(
+            spans(
+                LineState::Code(Language::Json),
+                "{\"ok\": true, \"n\": 1.5}"
+            ),
+            [
+                ("\"ok\"", Highlight::Key),
+                ("true", Highlight::Keyword),
+                ("\"n\"", Highlight::Key),
+                ("1.5", Highlight::Number),
+            ]
+        );
+        assert_eq!(
+            spans(LineState::Code(Language::Yaml), "  - name: 'x' # note"),
+            [
+                ("name", Highlight::Key),
+                ("'x'", Highlight::String),
+                ("# note", Highlight::Comment),
+            ]
+        );
+    }
+
+    #[test]
+    fn restarts_from_the_changed_line() {
+        let mut highlighter = Highlighter::new(&());
+        for line in ["text", "```py", "x = 1", "```", "more"] {
+            let _ = highlighter.highlight_line(line).count();
+        }
+        assert_eq!(highlighter.current_line(), 5);
+
+        highlighter.change_line(2);
+        assert_eq!(highlighter.current_line(), 2);
+        let highlights: Vec<_> = highlighter.highlight_line("import os").collect();
+        assert_eq!(highlights, [(0..6, Highlight::Keyword)]);
+    }
+}
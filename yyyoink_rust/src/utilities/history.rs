@@ -0,0 +1,463 @@
+use super::file;
+use super::index;
+use super::tool;
+use super::trash::{self, TrashItem};
+use crate::enums::error::Error;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+/// How many operations can be undone before the oldest is forgotten.
+const HISTORY_LIMIT: usize = 50;
+/// How many bytes of file contents the journal keeps before the oldest entries are forgotten.
+/// An operation larger than this on its own, e.g. a tag renamed across a big vault, is not kept.
+const HISTORY_BYTES_LIMIT: usize = 16 * 1024 * 1024;
+
+/// One file's contents before and after an operation. `None` means the file did not exist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileChange {
+    pub file: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+/// A journaled operation: every file it touched, so it can be reverted and replayed as a whole.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub label: String,
+    pub root: PathBuf,
+    pub changes: Vec<FileChange>,
+}
+
+impl HistoryEntry {
+    /// Bytes of file contents the entry keeps.
+    pub fn size(&self) -> usize {
+        self.changes
+            .iter()
+            .flat_map(|change| [&change.before, &change.after])
+            .map(|text| text.as_ref().map_or(0, String::len))
+            .sum()
+    }
+}
+
+/// Bounded undo and redo stacks. Recording a new operation clears the redo stack.
+#[derive(Debug, Default)]
+pub struct History {
+    undo: VecDeque<HistoryEntry>,
+    redo: Vec<HistoryEntry>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Journals an entry, or returns `false` if it is too large to keep.
+    pub fn push(&mut self, entry: HistoryEntry) -> bool {
+        self.redo.clear();
+        if entry.size() > HISTORY_BYTES_LIMIT {
+            eprintln!(
+                "Not journaling {}, it keeps {} bytes.",
+                entry.label,
+                entry.size()
+            );
+            return false;
+        }
+        self.push_undo(entry);
+        true
+    }
+
+    fn push_undo(&mut self, entry: HistoryEntry) {
+        self.undo.push_back(entry);
+        while self.undo.len() > HISTORY_LIMIT
+            || (self.undo.len() > 1 && self.size() > HISTORY_BYTES_LIMIT)
+        {
+            self.undo.pop_front();
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.undo
+            .iter()
+            .chain(&self.redo)
+            .map(HistoryEntry::size)
+            .sum()
+    }
+
+    pub fn take_undo(&mut self) -> Option<HistoryEntry> {
+        self.undo.pop_back()
+    }
+
+    pub fn take_redo(&mut self) -> Option<HistoryEntry> {
+        self.redo.pop()
+    }
+
+    /// Files an entry after it was undone, or puts it back if undoing it failed.
+    pub fn undone(&mut self, entry: HistoryEntry, result: &Result<bool, Error>) {
+        match result {
+            Ok(_) => self.redo.push(entry),
+            Err(_) => self.push_undo(entry),
+        }
+    }
+
+    /// Files an entry after it was redone, or puts it back if redoing it failed.
+    pub fn redone(&mut self, entry: HistoryEntry, result: &Result<bool, Error>) {
+        match result {
+            Ok(_) => self.push_undo(entry),
+            Err(_) => self.redo.push(entry),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+/// Reads a file of the vault, `None` if it does not exist or cannot be read.
+pub async fn snapshot(root: &Path, file: &str) -> Option<String> {
+    fs::read_to_string(tool::vault_path(root, file).ok()?)
+        .await
+        .ok()
+}
+
+/// Runs `operation`, snapshotting `files` around it. Returns the operation's result and, if it
+/// succeeded and changed anything, the entry that undoes it.
+pub async fn record<T>(
+    root: PathBuf,
+    label: String,
+    files: Vec<String>,
+    operation: impl Future<Output = Result<T, Error>>,
+) -> (Result<T, Error>, Option<HistoryEntry>) {
+    let mut before: Vec<Option<String>> = Vec::new();
+    for file in &files {
+        before.push(snapshot(&root, file).await);
+    }
+
+    let result = operation.await;
+    if result.is_err() {
+        return (result, None);
+    }
+
+    let mut changes: Vec<FileChange> = Vec::new();
+    for (file, before) in files.into_iter().zip(before) {
+        let after = snapshot(&root, &file).await;
+        if before != after {
+            changes.push(FileChange {
+                file,
+                before,
+                after,
+            });
+        }
+    }
+
+    if changes.is_empty() {
+        (result, None)
+    } else {
+        (
+            result,
+            Some(HistoryEntry {
+                label,
+                root,
+                changes,
+            }),
+        )
+    }
+}
+
+/// Like `record`, for deletes that keep a copy in the trash. The trash entry is journaled too,
+/// so undoing the delete does not leave a stale copy behind to be restored twice.
+pub async fn record_trashed(
+    root: PathBuf,
+    label: String,
+    files: Vec<String>,
+    operation: impl Future<Output = Result<TrashItem, Error>>,
+) -> (Result<bool, Error>, Option<HistoryEntry>) {
+    let (result, mut entry) = record(root, label, files, operation).await;
+    if let (Ok(item), Some(entry)) = (&result, &mut entry) {
+        entry.changes.push(FileChange {
+            file: trash::trash_file(&item.id),
+            before: None,
+            after: Some(item.to_text()),
+        });
+    }
+    (result.map(|_| true), entry)
+}
+
+/// Puts every file of `entry` back the way it was before the operation.
+pub async fn undo(entry: HistoryEntry) -> Result<bool, Error> {
+    let changes = entry
+        .changes
+        .iter()
+        .rev()
+        .map(|change| (change.file.as_str(), &change.after, &change.before));
+    apply(&entry.root, changes.collect()).await
+}
+
+/// Replays the operation of `entry` after it was undone.
+pub async fn redo(entry: HistoryEntry) -> Result<bool, Error> {
+    let changes = entry
+        .changes
+        .iter()
+        .map(|change| (change.file.as_str(), &change.before, &change.after));
+    apply(&entry.root, changes.collect()).await
+}
+
+/// Moves each file from its expected contents to its target contents. Nothing is written unless
+/// every file still holds what the journal expects, so edits made since are never clobbered.
+async fn apply(
+    root: &Path,
+    changes: Vec<(&str, &Option<String>, &Option<String>)>,
+) -> Result<bool, Error> {
+    for (file, expected, _) in &changes {
+        if snapshot(root, file).await != **expected {
+            eprintln!("Refusing to rewrite {}, it changed since.", file);
+            return Err(Error::FileChanged);
+        }
+    }
+
+    for (file, _, target) in changes {
+        match target {
+            Some(text) => {
+                file::write_file(root.to_path_buf(), file.to_string(), text.clone()).await?;
+            }
+            None => {
+                let path = tool::vault_path(root, file)?;
+                if let Err(e) = fs::remove_file(&path).await {
+                    if e.kind() != ErrorKind::NotFound {
+                        eprintln!(
+                            "Failed to remove {}.\nUnderlying error: {}",
+                            path.display(),
+                            e
+                        );
+                        return Err(Error::IoError(e.kind()));
+                    }
+                }
+                index::update_file(root, file).await;
+            }
+        }
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::capture_record::{CaptureHeader, CaptureRecord};
+
+    async fn vault() -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("yyyoink-history-{}", CaptureHeader::new_id()));
+        fs::create_dir_all(&root).await.unwrap();
+        fs::write(root.join("_work.md"), "first\n").await.unwrap();
+        root
+    }
+
+    #[tokio::test]
+    async fn undo_and_redo_a_recorded_write() {
+        let root = vault().await;
+        let (result, entry) = record(
+            root.clone(),
+            "update".to_string(),
+            vec!["_work.md".to_string(), "_new.md".to_string()],
+            async {
+                file::write_file(root.clone(), "_work.md".to_string(), "second\n".to_string())
+                    .await?;
+                file::append_file(root.clone(), "_new.md".to_string(), "new\n".to_string()).await
+            },
+        )
+        .await;
+        assert!(result.is_ok());
+        let entry = entry.unwrap();
+        assert_eq!(entry.changes.len(), 2);
+
+        undo(entry.clone()).await.unwrap();
+        assert_eq!(
+            snapshot(&root, "_work.md").await.as_deref(),
+            Some("first\n")
+        );
+        assert_eq!(snapshot(&root, "_new.md").await, None);
+
+        redo(entry).await.unwrap();
+        assert_eq!(
+            snapshot(&root, "_work.md").await.as_deref(),
+            Some("second\n")
+        );
+        assert_eq!(snapshot(&root, "_new.md").await.as_deref(), Some("new\n"));
+
+        fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn undo_refuses_files_changed_since() {
+        let root = vault().await;
+        let (_, entry) = record(
+            root.clone(),
+            "update".to_string(),
+            vec!["_work.md".to_string()],
+            file::write_file(root.clone(), "_work.md".to_string(), "second\n".to_string()),
+        )
+        .await;
+        fs::write(root.join("_work.md"), "edited elsewhere\n")
+            .await
+            .unwrap();
+
+        assert!(undo(entry.unwrap()).await.is_err());
+        assert_eq!(
+            snapshot(&root, "_work.md").await.as_deref(),
+            Some("edited elsewhere\n")
+        );
+
+        fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    const CAPTURES: &str =
+        "<!--yoink::::2025-04-01T09:00:00+00:00::::work::::standup::::id=01JQHIST0000000000000000001-->
+yesterday: shipped parser
+<!--yoink::::2025-04-02T09:00:00+00:00::::work::::retro::::id=01JQHIST0000000000000000002-->
+went well: tests
+";
+
+    async fn capture_vault() -> (PathBuf, CaptureRecord) {
+        let root = vault().await;
+        fs::write(root.join("_work.md"), CAPTURES).await.unwrap();
+        let standup = file::load_captures(root.clone())
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|record| record.header.subject == "standup")
+            .unwrap();
+        (root, standup)
+    }
+
+    #[tokio::test]
+    async fn undo_and_redo_a_deleted_capture() {
+        let (root, standup) = capture_vault().await;
+        let (result, entry) = record_trashed(
+            root.clone(),
+            "delete capture".to_string(),
+            vec!["_work.md".to_string()],
+            file::delete_capture(root.clone(), standup),
+        )
+        .await;
+        assert!(result.is_ok());
+        let entry = entry.unwrap();
+        let trashed = entry.changes.last().unwrap().file.clone();
+        let deleted = snapshot(&root, "_work.md").await.unwrap();
+        assert!(!deleted.contains("standup"));
+
+        undo(entry.clone()).await.unwrap();
+        assert_eq!(snapshot(&root, "_work.md").await.as_deref(), Some(CAPTURES));
+        assert_eq!(snapshot(&root, &trashed).await, None);
+
+        redo(entry).await.unwrap();
+        assert_eq!(snapshot(&root, "_work.md").await, Some(deleted));
+        assert!(snapshot(&root, &trashed).await.is_some());
+
+        fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn undo_and_redo_a_moved_capture() {
+        let (root, standup) = capture_vault().await;
+        let (result, entry) = record(
+            root.clone(),
+            "move capture".to_string(),
+            vec!["_work.md".to_string(), "_home.md".to_string()],
+            file::move_capture(root.clone(), standup, "home".to_string()),
+        )
+        .await;
+        assert!(result.is_ok());
+        let entry = entry.unwrap();
+        let moved = snapshot(&root, "_home.md").await.unwrap();
+        assert!(moved.contains("::::home::::standup"));
+
+        undo(entry.clone()).await.unwrap();
+        assert_eq!(snapshot(&root, "_work.md").await.as_deref(), Some(CAPTURES));
+        assert_eq!(snapshot(&root, "_home.md").await, None);
+
+        redo(entry).await.unwrap();
+        assert_eq!(snapshot(&root, "_home.md").await, Some(moved));
+        assert!(!snapshot(&root, "_work.md")
+            .await
+            .unwrap()
+            .contains("standup"));
+
+        fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn undo_a_deleted_file_restores_it_and_drops_the_trash_entry() {
+        let root = vault().await;
+        let (result, entry) = record_trashed(
+            root.clone(),
+            "delete file".to_string(),
+            vec!["_work.md".to_string()],
+            file::delete_file(root.clone(), "_work.md".to_string()),
+        )
+        .await;
+        assert!(result.is_ok());
+        let entry = entry.unwrap();
+        let trashed = entry.changes.last().unwrap().file.clone();
+        assert!(snapshot(&root, &trashed).await.is_some());
+
+        undo(entry.clone()).await.unwrap();
+        assert_eq!(
+            snapshot(&root, "_work.md").await.as_deref(),
+            Some("first\n")
+        );
+        assert_eq!(snapshot(&root, &trashed).await, None);
+
+        redo(entry).await.unwrap();
+        assert_eq!(snapshot(&root, "_work.md").await, None);
+        assert!(snapshot(&root, &trashed).await.is_some());
+
+        fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[test]
+    fn history_keeps_a_bounded_number_of_bytes() {
+        let entry = |label: &str, bytes: usize| HistoryEntry {
+            label: label.to_string(),
+            root: PathBuf::new(),
+            changes: vec![FileChange {
+                file: "_work.md".to_string(),
+                before: None,
+                after: Some("x".repeat(bytes)),
+            }],
+        };
+        let mut history = History::new();
+        assert!(history.push(entry("small", 10)));
+        assert!(!history.push(entry("vault-wide", HISTORY_BYTES_LIMIT + 1)));
+        assert_eq!(history.undo.len(), 1);
+
+        assert!(history.push(entry("half", HISTORY_BYTES_LIMIT / 2)));
+        assert!(history.push(entry("other half", HISTORY_BYTES_LIMIT / 2)));
+        assert_eq!(history.undo.front().unwrap().label, "half");
+        assert!(history.size() <= HISTORY_BYTES_LIMIT);
+    }
+
+    #[test]
+    fn history_is_bounded_and_new_entries_clear_redo() {
+        let entry = |label: usize| HistoryEntry {
+            label: label.to_string(),
+            root: PathBuf::new(),
+            changes: Vec::new(),
+        };
+        let mut history = History::new();
+        for label in 0..HISTORY_LIMIT + 5 {
+            history.push(entry(label));
+        }
+        assert_eq!(history.undo.len(), HISTORY_LIMIT);
+        assert_eq!(history.undo.front().unwrap().label, "5");
+
+        let undone = history.take_undo().unwrap();
+        history.undone(undone, &Ok(true));
+        assert_eq!(history.redo.len(), 1);
+        history.push(entry(99));
+        assert!(history.take_redo().is_none());
+    }
+}
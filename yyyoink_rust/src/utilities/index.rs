@@ -0,0 +1,687 @@
+use super::file;
+use super::tool;
+use crate::capture::capture_record::{self, CaptureHeader, CaptureRecord};
+use crate::enums::error::Error;
+use std::collections::{HashMap, HashSet};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::UNIX_EPOCH;
+use tokio::fs;
+use tokio::sync::Mutex;
+
+const INDEX_DIR: &str = ".index";
+const SEGMENT_EXTENSION: &str = "idx";
+const SEGMENT_MAGIC: &str = "yoink-index 1";
+const MAX_TERM_LENGTH: usize = 64;
+const SNIPPET_BEFORE: usize = 30;
+const SNIPPET_AFTER: usize = 90;
+
+/// BM25 tuning, the usual defaults.
+const BM25_K1: f64 = 1.2;
+const BM25_B: f64 = 0.75;
+
+/// One index per vault root, loaded on first use and kept in memory.
+static INDEXES: OnceLock<Mutex<HashMap<PathBuf, SearchIndex>>> = OnceLock::new();
+
+/// Size and modification time of a topic file when it was indexed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signature {
+    pub len: u64,
+    pub modified: u128,
+}
+
+/// A capture body in the index.
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub file_name: String,
+    pub header: CaptureHeader,
+    pub body: String,
+    pub terms: Vec<(String, u32)>,
+    pub length: u32,
+}
+
+/// A capture whose body matched, with a preview around the first matching word.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexHit {
+    pub record: CaptureRecord,
+    pub score: f64,
+    pub snippet: String,
+    pub highlights: Vec<usize>,
+}
+
+#[derive(Debug, Clone)]
+struct IndexedFile {
+    signature: Option<Signature>,
+    documents: Vec<usize>,
+}
+
+/// An inverted index over capture bodies. Every topic file is indexed on its own and persisted
+/// as `<vault>/.index/<file>.idx`, so a save only re-indexes and rewrites the file it touched.
+#[derive(Debug, Default)]
+pub struct SearchIndex {
+    documents: Vec<Option<Document>>,
+    free: Vec<usize>,
+    files: HashMap<String, IndexedFile>,
+    postings: HashMap<String, Vec<(usize, u32)>>,
+    total_length: u64,
+    live: usize,
+}
+
+impl SearchIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn capture_count(&self) -> usize {
+        self.live
+    }
+
+    pub fn signature(&self, file_name: &str) -> Option<Signature> {
+        self.files.get(file_name).and_then(|file| file.signature)
+    }
+
+    /// Re-indexes every capture of a topic file, replacing what was indexed for it before.
+    pub fn index_file(&mut self, file_name: &str, content: &str, signature: Option<Signature>) {
+        let documents = capture_record::parse_sections(content)
+            .into_iter()
+            .map(|section| {
+                let body = section.body().to_string();
+                let terms = term_counts(&body);
+                Document {
+                    file_name: file_name.to_string(),
+                    header: section.header,
+                    length: terms.iter().map(|(_, count)| count).sum(),
+                    body,
+                    terms,
+                }
+            })
+            .collect();
+        self.insert_file(file_name, documents, signature);
+    }
+
+    fn insert_file(
+        &mut self,
+        file_name: &str,
+        documents: Vec<Document>,
+        signature: Option<Signature>,
+    ) {
+        self.remove_file(file_name);
+
+        let mut ids: Vec<usize> = Vec::new();
+        for document in documents {
+            let id = match self.free.pop() {
+                Some(id) => id,
+                None => {
+                    self.documents.push(None);
+                    self.documents.len() - 1
+                }
+            };
+            for (term, count) in &document.terms {
+                self.postings
+                    .entry(term.clone())
+                    .or_default()
+                    .push((id, *count));
+            }
+            self.total_length += document.length as u64;
+            self.live += 1;
+            self.documents[id] = Some(document);
+            ids.push(id);
+        }
+
+        self.files.insert(
+            file_name.to_string(),
+            IndexedFile {
+                signature,
+                documents: ids,
+            },
+        );
+    }
+
+    pub fn remove_file(&mut self, file_name: &str) {
+        let Some(indexed) = self.files.remove(file_name) else {
+            return;
+        };
+
+        let ids: HashSet<usize> = indexed.documents.iter().copied().collect();
+        let mut terms: HashSet<String> = HashSet::new();
+        for &id in &indexed.documents {
+            if let Some(document) = self.documents[id].take() {
+                self.total_length -= document.length as u64;
+                self.live -= 1;
+                terms.extend(document.terms.into_iter().map(|(term, _)| term));
+            }
+            self.free.push(id);
+        }
+        for term in terms {
+            if let Some(postings) = self.postings.get_mut(&term) {
+                postings.retain(|(id, _)| !ids.contains(id));
+                if postings.is_empty() {
+                    self.postings.remove(&term);
+                }
+            }
+        }
+    }
+
+    /// Ranks captures by BM25 over the words of `query`, best first.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<IndexHit> {
+        let terms: Vec<String> = tokenize(query)
+            .map(|(_, term)| term)
+            .collect::<HashSet<String>>()
+            .into_iter()
+            .collect();
+        if terms.is_empty() || self.live == 0 || limit == 0 {
+            return Vec::new();
+        }
+
+        let documents = self.live as f64;
+        let average_length = (self.total_length as f64 / documents).max(1.0);
+        let mut scores: HashMap<usize, f64> = HashMap::new();
+        for term in &terms {
+            let Some(postings) = self.postings.get(term) else {
+                continue;
+            };
+            let frequency = postings.len() as f64;
+            let idf = (1.0 + (documents - frequency + 0.5) / (frequency + 0.5)).ln();
+            for &(id, count) in postings {
+                let Some(document) = &self.documents[id] else {
+                    continue;
+                };
+                let count = count as f64;
+                let norm = 1.0 - BM25_B + BM25_B * document.length as f64 / average_length;
+                *scores.entry(id).or_default() +=
+                    idf * count * (BM25_K1 + 1.0) / (count + BM25_K1 * norm);
+            }
+        }
+
+        let mut ranked: Vec<(usize, f64)> = scores.into_iter().collect();
+        let by_score = |a: &(usize, f64), b: &(usize, f64)| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0));
+        if ranked.len() > limit {
+            ranked.select_nth_unstable_by(limit - 1, by_score);
+            ranked.truncate(limit);
+        }
+        ranked.sort_unstable_by(by_score);
+
+        ranked
+            .into_iter()
+            .filter_map(|(id, score)| {
+                let document = self.documents[id].as_ref()?;
+                let (snippet, highlights) = snippet(&document.body, &terms);
+                Some(IndexHit {
+                    record: CaptureRecord {
+                        header: document.header.clone(),
+                        file_name: document.file_name.clone(),
+                        tags: capture_record::capture_tags(&document.header, &document.body),
+                    },
+                    score,
+                    snippet,
+                    highlights,
+                })
+            })
+            .collect()
+    }
+
+    fn segment_text(&self, file_name: &str) -> Option<String> {
+        let indexed = self.files.get(file_name)?;
+        let signature = indexed.signature.unwrap_or(Signature {
+            len: 0,
+            modified: 0,
+        });
+        let mut text = format!(
+            "{} {} {}\n",
+            SEGMENT_MAGIC, signature.len, signature.modified
+        );
+        for document in indexed
+            .documents
+            .iter()
+            .filter_map(|&id| self.documents[id].as_ref())
+        {
+            let terms: Vec<String> = document
+                .terms
+                .iter()
+                .map(|(term, count)| format!("{}:{}", term, count))
+                .collect();
+            text.push_str(&format!(
+                "d {}\nb {}\nt {}\n",
+                document.header,
+                escape(&document.body),
+                terms.join(" ")
+            ));
+        }
+        Some(text)
+    }
+
+    fn load_segment(&mut self, file_name: &str, text: &str) -> Option<()> {
+        let mut lines = text.lines();
+        let mut magic = lines.next()?.rsplitn(3, ' ');
+        let modified = magic.next()?.parse().ok()?;
+        let len = magic.next()?.parse().ok()?;
+        if magic.next()? != SEGMENT_MAGIC {
+            return None;
+        }
+
+        let mut documents: Vec<Document> = Vec::new();
+        while let Some(line) = lines.next() {
+            let header = CaptureHeader::parse(line.strip_prefix("d ")?)?;
+            let body = unescape(lines.next()?.strip_prefix("b ")?);
+            let mut terms: Vec<(String, u32)> = Vec::new();
+            for pair in lines.next()?.strip_prefix("t")?.split_whitespace() {
+                let (term, count) = pair.rsplit_once(':')?;
+                terms.push((term.to_string(), count.parse().ok()?));
+            }
+            documents.push(Document {
+                file_name: file_name.to_string(),
+                header,
+                body,
+                length: terms.iter().map(|(_, count)| count).sum(),
+                terms,
+            });
+        }
+
+        self.insert_file(file_name, documents, Some(Signature { len, modified }));
+        Some(())
+    }
+}
+
+/// Lowercased words of at least two characters, with their byte offset in `text`.
+fn tokenize(text: &str) -> impl Iterator<Item = (usize, String)> + '_ {
+    let mut start: Option<usize> = None;
+    text.char_indices()
+        .chain(std::iter::once((text.len(), ' ')))
+        .filter_map(move |(i, c)| {
+            if c.is_alphanumeric() {
+                start.get_or_insert(i);
+                None
+            } else {
+                let word = &text[start.take()?..i];
+                if word.chars().nth(1).is_none() || word.len() > MAX_TERM_LENGTH {
+                    None
+                } else {
+                    Some((i - word.len(), word.to_lowercase()))
+                }
+            }
+        })
+}
+
+fn term_counts(text: &str) -> Vec<(String, u32)> {
+    let mut counts: HashMap<String, u32> = HashMap::new();
+    for (_, term) in tokenize(text) {
+        *counts.entry(term).or_default() += 1;
+    }
+    let mut counts: Vec<(String, u32)> = counts.into_iter().collect();
+    counts.sort_unstable();
+    counts
+}
+
+/// A single-line preview around the first word of `body` that matches, with the character
+/// positions of matching words.
+fn snippet(body: &str, terms: &[String]) -> (String, Vec<usize>) {
+    let words: Vec<(usize, String)> = tokenize(body).collect();
+    let first = words
+        .iter()
+        .find(|(_, word)| terms.contains(word))
+        .map(|(offset, _)| *offset)
+        .unwrap_or(0);
+
+    let chars: Vec<(usize, char)> = body.char_indices().collect();
+    let center = chars.partition_point(|(offset, _)| *offset < first);
+    let start = center.saturating_sub(SNIPPET_BEFORE);
+    let end = (center + SNIPPET_AFTER).min(chars.len());
+
+    let mut snippet = String::new();
+    let mut highlights: Vec<usize> = Vec::new();
+    if start > 0 {
+        snippet.push('…');
+    }
+    let offset = snippet.chars().count();
+    for (i, &(_, c)) in chars[start..end].iter().enumerate() {
+        snippet.push(if c.is_whitespace() { ' ' } else { c });
+        let byte = chars[start + i].0;
+        if words.iter().any(|(word_start, word)| {
+            terms.contains(word) && byte >= *word_start && byte < word_start + word.len()
+        }) {
+            highlights.push(offset + i);
+        }
+    }
+    if end < chars.len() {
+        snippet.push('…');
+    }
+
+    (snippet, highlights)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') => result.push('\n'),
+                Some('r') => result.push('\r'),
+                Some(other) => result.push(other),
+                None => result.push('\\'),
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+fn is_topic_file(file_name: &str) -> bool {
+    file_name.starts_with('_') && file_name.ends_with(".md") && !file_name.contains(['/', '\\'])
+}
+
+fn segment_path(root: &Path, file_name: &str) -> PathBuf {
+    root.join(INDEX_DIR)
+        .join(format!("{}.{}", file_name, SEGMENT_EXTENSION))
+}
+
+impl Signature {
+    pub fn from_metadata(meta: &std::fs::Metadata) -> Option<Self> {
+        let modified = meta
+            .modified()
+            .ok()?
+            .duration_since(UNIX_EPOCH)
+            .ok()?
+            .as_nanos();
+        Some(Self {
+            len: meta.len(),
+            modified,
+        })
+    }
+}
+
+async fn signature(path: &Path) -> Option<Signature> {
+    Signature::from_metadata(&fs::metadata(path).await.ok()?)
+}
+
+/// Reads every persisted segment of a vault.
+async fn load(root: &Path) -> SearchIndex {
+    let mut index = SearchIndex::new();
+    let Ok(mut entries) = fs::read_dir(root.join(INDEX_DIR)).await else {
+        return index;
+    };
+
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let Some(file_name) = name.strip_suffix(&format!(".{}", SEGMENT_EXTENSION)) else {
+            continue;
+        };
+        let loaded = match fs::read_to_string(entry.path()).await {
+            Ok(text) => index.load_segment(file_name, &text),
+            Err(_) => None,
+        };
+        if loaded.is_none() {
+            eprintln!("Skipping unreadable index segment {}.", name);
+        }
+    }
+
+    index
+}
+
+async fn persist(root: &Path, index: &SearchIndex, file_name: &str) {
+    let path = segment_path(root, file_name);
+    let result = match index.segment_text(file_name) {
+        Some(text) => match fs::create_dir_all(root.join(INDEX_DIR)).await {
+            Ok(_) => file::write_atomic(&path, text.as_bytes()).await,
+            Err(e) => Err(e),
+        },
+        None => match fs::remove_file(&path).await {
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            result => result,
+        },
+    };
+    if let Err(e) = result {
+        eprintln!(
+            "Failed to persist index segment {}.\nUnderlying error: {}",
+            path.display(),
+            e
+        );
+    }
+}
+
+/// Locks the vault's index, loading it from disk on first use.
+async fn lock(root: &Path) -> tokio::sync::MappedMutexGuard<'static, SearchIndex> {
+    let mut indexes = INDEXES
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .await;
+    if !indexes.contains_key(root) {
+        let index = load(root).await;
+        indexes.insert(root.to_path_buf(), index);
+    }
+    tokio::sync::MutexGuard::map(indexes, |indexes| indexes.get_mut(root).unwrap())
+}
+
+async fn update_locked(root: &Path, index: &mut SearchIndex, file_name: &str) {
+    let path = tool::source_path(root, file_name.to_string());
+    let signature = signature(&path).await;
+    match fs::read_to_string(&path).await {
+        Ok(content) => index.index_file(file_name, &content, signature),
+        Err(_) => index.remove_file(file_name),
+    }
+    persist(root, index, file_name).await;
+}
+
+/// Re-indexes one topic file after it was written, or drops it if it is gone.
+pub async fn update_file(root: &Path, file_name: &str) {
+    if !is_topic_file(file_name) {
+        return;
+    }
+    let mut index = lock(root).await;
+    update_locked(root, &mut index, file_name).await;
+}
+
+/// Brings the index in line with the topic files on disk, re-indexing only files whose size or
+/// modification time changed since they were indexed.
+pub async fn sync(root: &Path, file_names: &[String]) {
+    let mut index = lock(root).await;
+    let mut updated = 0;
+
+    for file_name in file_names.iter().filter(|name| is_topic_file(name)) {
+        let current = signature(&tool::source_path(root, file_name.clone())).await;
+        if current.is_none() || current != index.signature(file_name) {
+            update_locked(root, &mut index, file_name).await;
+            updated += 1;
+        }
+    }
+
+    let stale: Vec<String> = index
+        .files
+        .keys()
+        .filter(|name| !file_names.contains(name))
+        .cloned()
+        .collect();
+    for file_name in stale {
+        index.remove_file(&file_name);
+        persist(root, &index, &file_name).await;
+        updated += 1;
+    }
+
+    if updated > 0 {
+        println!(
+            "Re-indexed {} topic files, {} captures indexed.",
+            updated,
+            index.capture_count()
+        );
+    }
+}
+
+/// Searches capture bodies of a vault.
+pub async fn search(root: PathBuf, query: String, limit: usize) -> Result<Vec<IndexHit>, Error> {
+    let index = lock(&root).await;
+    Ok(index.search(&query, limit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    const WORK: &str =
+        "<!--yoink::::2025-04-01 09:00:00::::work::::standup::::id=01JQIDX00000000000000000001-->
+Yesterday I fixed the parser.
+Today: release notes for the parser rewrite.
+<!--yoink::::2025-04-02 09:00:00::::work::::retro::::id=01JQIDX00000000000000000002-->
+Went well: tests. Went badly: the release slipped.
+";
+
+    async fn vault() -> PathBuf {
+        let root = std::env::temp_dir().join(format!("yyyoink-index-{}", CaptureHeader::new_id()));
+        fs::create_dir_all(&root).await.unwrap();
+        fs::write(root.join("_work.md"), WORK).await.unwrap();
+        root
+    }
+
+    #[test]
+    fn ranks_bodies_and_builds_snippets() {
+        let mut index = SearchIndex::new();
+        index.index_file("_work.md", WORK, None);
+
+        let hits = index.search("Parser", 10);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].record.header.subject, "standup");
+        assert_eq!(hits[0].record.file_name, "_work.md");
+        assert!(hits[0]
+            .snippet
+            .starts_with("Yesterday I fixed the parser. Today"));
+        let highlighted: String = hits[0]
+            .highlights
+            .iter()
+            .map(|&i| hits[0].snippet.chars().nth(i).unwrap())
+            .collect();
+        assert_eq!(highlighted, "parserparser");
+
+        let hits = index.search("release went", 10);
+        assert_eq!(hits[0].record.header.subject, "retro");
+        assert_eq!(hits.len(), 2);
+    }
+
+    #[test]
+    fn reindexing_a_file_replaces_its_documents() {
+        let mut index = SearchIndex::new();
+        index.index_file("_work.md", WORK, None);
+        index.index_file(
+            "_home.md",
+            "<!--yoink::::2025-04-01 18:00:00::::home::::groceries-->\nparser of eggs\n",
+            None,
+        );
+        assert_eq!(index.search("parser", 10).len(), 2);
+
+        index.index_file("_work.md", "", None);
+        assert_eq!(index.capture_count(), 1);
+        assert_eq!(index.search("parser", 10)[0].record.file_name, "_home.md");
+        assert!(index.search("release", 10).is_empty());
+    }
+
+    #[tokio::test]
+    async fn segments_persist_and_writes_update_them() {
+        let root = vault().await;
+        sync(&root, &["_work.md".to_string()]).await;
+
+        let loaded = load(&root).await;
+        assert_eq!(loaded.capture_count(), 2);
+        assert_eq!(
+            loaded.search("slipped", 10)[0].record.header.subject,
+            "retro"
+        );
+
+        file::append_file(
+            root.clone(),
+            "_work.md".to_string(),
+            "<!--yoink::::2025-04-03 09:00:00::::work::::planning-->\nroadmap \\ backslash\n"
+                .to_string(),
+        )
+        .await
+        .unwrap();
+        let hits = search(root.clone(), "roadmap".to_string(), 10)
+            .await
+            .unwrap();
+        assert_eq!(hits[0].record.header.subject, "planning");
+        let loaded = load(&root).await;
+        assert_eq!(loaded.capture_count(), 3);
+        assert!(loaded.search("roadmap", 1)[0]
+            .snippet
+            .contains("roadmap \\ backslash"));
+
+        fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    /// Indexes `captures` synthetic captures of 30 random words each, spread over `files` files.
+    fn synthetic_index(captures: usize, files: usize) -> SearchIndex {
+        const WORDS: [&str; 16] = [
+            "parser", "release", "meeting", "budget", "garden", "recipe", "deploy", "invoice",
+            "travel", "review", "backup", "kernel", "sprint", "design", "metric", "ticket",
+        ];
+
+        let mut seed: u64 = 0x9e3779b97f4a7c15;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+
+        let mut index = SearchIndex::new();
+        for file in 0..files {
+            let mut content = String::new();
+            for capture in 0..captures / files {
+                content.push_str(&format!(
+                    "<!--yoink::::2025-04-01 09:00:00::::topic{}::::capture {}-->\n",
+                    file, capture
+                ));
+                for _ in 0..30 {
+                    content.push_str(WORDS[(next() % WORDS.len() as u64) as usize]);
+                    content.push(' ');
+                }
+                content.push_str(&format!("unique{}x{}\n", file, capture));
+            }
+            index.index_file(&format!("_topic{}.md", file), &content, None);
+        }
+        index
+    }
+
+    #[test]
+    fn finds_captures_in_a_synthetic_corpus() {
+        let index = synthetic_index(2_000, 10);
+        assert_eq!(index.capture_count(), 2_000);
+        assert_eq!(index.search("parser release budget", 20).len(), 20);
+        let rare = index.search("unique7x123", 20);
+        assert_eq!(rare.len(), 1);
+        assert_eq!(rare[0].record.header.subject, "capture 123");
+        assert_eq!(rare[0].record.file_name, "_topic7.md");
+    }
+
+    /// Checks that queries on a 50k capture corpus stay in the milliseconds. Timing depends on
+    /// the machine, so it only runs on request:
+    /// `cargo test --release benchmark -- --ignored --nocapture`.
+    #[test]
+    #[ignore = "benchmark, run with --release -- --ignored"]
+    fn benchmark_query_on_synthetic_corpus() {
+        let started = Instant::now();
+        let index = synthetic_index(50_000, 50);
+        println!(
+            "indexed {} captures in {:?}",
+            index.capture_count(),
+            started.elapsed()
+        );
+        assert_eq!(index.capture_count(), 50_000);
+
+        let started = Instant::now();
+        let common = index.search("parser release budget", 20);
+        let rare = index.search("unique7x123", 20);
+        let elapsed = started.elapsed();
+        println!("queried in {:?}", elapsed);
+
+        assert_eq!(common.len(), 20);
+        assert_eq!(rare[0].record.header.subject, "capture 123");
+        assert!(elapsed.as_millis() < 250, "queries took {:?}", elapsed);
+    }
+}
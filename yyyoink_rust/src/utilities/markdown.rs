@@ -0,0 +1,515 @@
+use crate::capture::capture_record::CaptureHeader;
+use pulldown_cmark::{Alignment, CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
+use std::collections::HashMap;
+
+/// A piece of styled text inside a block.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Run {
+    pub text: String,
+    pub strong: bool,
+    pub emphasis: bool,
+    pub strikethrough: bool,
+    pub code: bool,
+    pub link: Option<String>,
+}
+
+impl Run {
+    fn same_style(&self, other: &Run) -> bool {
+        self.strong == other.strong
+            && self.emphasis == other.emphasis
+            && self.strikethrough == other.strikethrough
+            && self.code == other.code
+            && self.link == other.link
+    }
+}
+
+/// A rendered markdown block. `Divider` stands in for a `<!--yoink-->` capture header.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    Divider(CaptureHeader),
+    Heading(u8, Vec<Run>),
+    Paragraph(Vec<Run>),
+    Quote(Vec<Block>),
+    List {
+        start: Option<u64>,
+        items: Vec<Vec<Block>>,
+    },
+    Code {
+        language: Option<String>,
+        text: String,
+    },
+    Table {
+        alignments: Vec<Alignment>,
+        header: Vec<Vec<Run>>,
+        rows: Vec<Vec<Vec<Run>>>,
+    },
+    Rule,
+}
+
+/// The preview of the editor text, kept as separately parsed chunks so an edit only re-parses
+/// the chunks it touched.
+#[derive(Debug, Default)]
+pub struct Preview {
+    chunks: Vec<(String, Vec<Block>)>,
+    definitions: String,
+}
+
+impl Preview {
+    /// Brings the preview up to date with `content`. Chunks whose text is unchanged keep their
+    /// blocks, unless a link reference definition changed. Returns how many chunks had to be
+    /// parsed.
+    pub fn update(&mut self, content: &str) -> usize {
+        let (chunks, definitions) = chunks(content);
+        let mut previous: HashMap<String, Vec<Block>> = self.chunks.drain(..).collect();
+        if definitions != self.definitions {
+            previous.clear();
+            self.definitions = definitions;
+        }
+        let mut parsed = 0;
+
+        for chunk in chunks {
+            let blocks = match previous.remove(chunk) {
+                Some(blocks) => blocks,
+                None => {
+                    parsed += 1;
+                    parse_chunk(chunk, &self.definitions)
+                }
+            };
+            self.chunks.push((chunk.to_string(), blocks));
+        }
+
+        parsed
+    }
+
+    pub fn blocks(&self) -> impl Iterator<Item = &Block> {
+        self.chunks.iter().flat_map(|(_, blocks)| blocks.iter())
+    }
+
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+        self.definitions.clear();
+    }
+}
+
+fn is_fence(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with("```") || line.starts_with("~~~")
+}
+
+/// Whether a line starts a list item, e.g. `- a` or `2. b`.
+fn is_list_item(line: &str) -> bool {
+    let digits = line.bytes().take_while(u8::is_ascii_digit).count();
+    let rest = match digits {
+        0 => line.strip_prefix(['-', '*', '+']),
+        _ => line[digits..].strip_prefix(['.', ')']),
+    };
+    rest.is_some_and(|rest| rest.is_empty() || rest.starts_with([' ', '\t']))
+}
+
+/// Whether a line is a link reference definition, e.g. `[docs]: https://example.com`.
+fn is_definition(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with('[') && line.contains("]:")
+}
+
+/// Splits markdown where parsing can restart without changing the result: around every capture
+/// header, and before an unindented line that follows a blank line, unless it continues a list
+/// or sits in a fenced code block or an HTML comment. Link reference definitions apply to the
+/// whole text, so they are returned as well, to be parsed along with every chunk.
+fn chunks(content: &str) -> (Vec<&str>, String) {
+    let mut chunks: Vec<&str> = Vec::new();
+    let mut definitions = String::new();
+    let mut start = 0;
+    let mut offset = 0;
+    let mut in_fence = false;
+    let mut in_comment = false;
+    let mut after_blank = false;
+
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim_end_matches(['\n', '\r']);
+        let is_header = CaptureHeader::parse(trimmed).is_some();
+        let starts_block = !in_fence
+            && !in_comment
+            && after_blank
+            && !trimmed.is_empty()
+            && !trimmed.starts_with(char::is_whitespace)
+            && !is_list_item(trimmed);
+
+        if (is_header || starts_block) && offset > start {
+            chunks.push(&content[start..offset]);
+            start = offset;
+        }
+        offset += line.len();
+
+        if is_header {
+            chunks.push(&content[start..offset]);
+            start = offset;
+            in_fence = false;
+            in_comment = false;
+        } else if in_comment {
+            in_comment = !trimmed.contains("-->");
+        } else if is_fence(trimmed) {
+            in_fence = !in_fence;
+        } else if !in_fence {
+            if is_definition(trimmed) {
+                definitions.push_str(trimmed);
+                definitions.push('\n');
+            }
+            in_comment = trimmed
+                .trim_start()
+                .strip_prefix("<!--")
+                .is_some_and(|rest| !rest.contains("-->"));
+        }
+        after_blank = trimmed.trim().is_empty();
+    }
+
+    if offset > start {
+        chunks.push(&content[start..offset]);
+    }
+    (chunks, definitions)
+}
+
+/// Parses one chunk with the text's link reference definitions in front of it. Definitions
+/// produce no blocks, so only the chunk's own blocks come out.
+fn parse_chunk(chunk: &str, definitions: &str) -> Vec<Block> {
+    match CaptureHeader::parse(chunk.trim_end_matches(['\n', '\r'])) {
+        Some(header) => vec![Block::Divider(header)],
+        None if definitions.is_empty() => parse(chunk),
+        None => parse(&format!("{}\n{}", definitions, chunk)),
+    }
+}
+
+/// A container being filled while walking the parser events.
+enum Frame {
+    Blocks(Vec<Block>),
+    Quote(Vec<Block>),
+    Item(Vec<Block>),
+    List {
+        start: Option<u64>,
+        items: Vec<Vec<Block>>,
+    },
+    Table {
+        alignments: Vec<Alignment>,
+        header: Vec<Vec<Run>>,
+        rows: Vec<Vec<Vec<Run>>>,
+    },
+    Row(Vec<Vec<Run>>),
+}
+
+#[derive(Default)]
+struct Builder {
+    frames: Vec<Frame>,
+    runs: Vec<Run>,
+    strong: usize,
+    emphasis: usize,
+    strikethrough: usize,
+    link: Option<String>,
+    code: Option<(Option<String>, String)>,
+}
+
+impl Builder {
+    fn push_block(&mut self, block: Block) {
+        if let Some(Frame::Blocks(blocks) | Frame::Quote(blocks) | Frame::Item(blocks)) =
+            self.frames.last_mut()
+        {
+            blocks.push(block)
+        }
+    }
+
+    /// Turns loose text, e.g. the text of a tight list item, into a paragraph.
+    fn flush(&mut self) {
+        if !self.runs.is_empty() {
+            let runs = std::mem::take(&mut self.runs);
+            self.push_block(Block::Paragraph(runs));
+        }
+    }
+
+    fn push_text(&mut self, text: &str, code: bool) {
+        let run = Run {
+            text: text.to_string(),
+            strong: self.strong > 0,
+            emphasis: self.emphasis > 0,
+            strikethrough: self.strikethrough > 0,
+            code,
+            link: self.link.clone(),
+        };
+        match self.runs.last_mut() {
+            Some(last) if last.same_style(&run) => last.text.push_str(text),
+            _ => self.runs.push(run),
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Heading { .. } | Tag::Paragraph => self.flush(),
+            Tag::BlockQuote(_) => {
+                self.flush();
+                self.frames.push(Frame::Quote(Vec::new()));
+            }
+            Tag::CodeBlock(kind) => {
+                self.flush();
+                let language = match kind {
+                    CodeBlockKind::Fenced(language) if !language.is_empty() => {
+                        Some(language.to_string())
+                    }
+                    _ => None,
+                };
+                self.code = Some((language, String::new()));
+            }
+            Tag::List(start) => {
+                self.flush();
+                self.frames.push(Frame::List {
+                    start,
+                    items: Vec::new(),
+                });
+            }
+            Tag::Item => {
+                self.flush();
+                self.frames.push(Frame::Item(Vec::new()));
+            }
+            Tag::Table(alignments) => {
+                self.flush();
+                self.frames.push(Frame::Table {
+                    alignments,
+                    header: Vec::new(),
+                    rows: Vec::new(),
+                });
+            }
+            Tag::TableHead | Tag::TableRow => self.frames.push(Frame::Row(Vec::new())),
+            Tag::TableCell => self.runs.clear(),
+            Tag::Emphasis => self.emphasis += 1,
+            Tag::Strong => self.strong += 1,
+            Tag::Strikethrough => self.strikethrough += 1,
+            Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. } => {
+                self.link = Some(dest_url.to_string())
+            }
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph => self.flush(),
+            TagEnd::Heading(level) => {
+                let runs = std::mem::take(&mut self.runs);
+                self.push_block(Block::Heading(level as u8, runs));
+            }
+            TagEnd::BlockQuote(_) => {
+                self.flush();
+                if let Some(Frame::Quote(blocks)) = self.frames.pop() {
+                    self.push_block(Block::Quote(blocks));
+                }
+            }
+            TagEnd::CodeBlock => {
+                if let Some((language, mut text)) = self.code.take() {
+                    if text.ends_with('\n') {
+                        text.pop();
+                    }
+                    self.push_block(Block::Code { language, text });
+                }
+            }
+            TagEnd::List(_) => {
+                if let Some(Frame::List { start, items }) = self.frames.pop() {
+                    self.push_block(Block::List { start, items });
+                }
+            }
+            TagEnd::Item => {
+                self.flush();
+                if let Some(Frame::Item(blocks)) = self.frames.pop() {
+                    if let Some(Frame::List { items, .. }) = self.frames.last_mut() {
+                        items.push(blocks);
+                    }
+                }
+            }
+            TagEnd::TableCell => {
+                let runs = std::mem::take(&mut self.runs);
+                if let Some(Frame::Row(cells)) = self.frames.last_mut() {
+                    cells.push(runs);
+                }
+            }
+            TagEnd::TableHead | TagEnd::TableRow => {
+                if let Some(Frame::Row(cells)) = self.frames.pop() {
+                    if let Some(Frame::Table { header, rows, .. }) = self.frames.last_mut() {
+                        if matches!(tag, TagEnd::TableHead) {
+                            *header = cells;
+                        } else {
+                            rows.push(cells);
+                        }
+                    }
+                }
+            }
+            TagEnd::Table => {
+                if let Some(Frame::Table {
+                    alignments,
+                    header,
+                    rows,
+                }) = self.frames.pop()
+                {
+                    self.push_block(Block::Table {
+                        alignments,
+                        header,
+                        rows,
+                    });
+                }
+            }
+            TagEnd::Emphasis => self.emphasis = self.emphasis.saturating_sub(1),
+            TagEnd::Strong => self.strong = self.strong.saturating_sub(1),
+            TagEnd::Strikethrough => self.strikethrough = self.strikethrough.saturating_sub(1),
+            TagEnd::Link | TagEnd::Image => self.link = None,
+            _ => {}
+        }
+    }
+}
+
+/// Parses markdown into blocks. HTML is dropped.
+pub fn parse(markdown: &str) -> Vec<Block> {
+    let options =
+        Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let mut builder = Builder {
+        frames: vec![Frame::Blocks(Vec::new())],
+        ..Builder::default()
+    };
+
+    for event in Parser::new_ext(markdown, options) {
+        match event {
+            Event::Start(tag) => builder.start(tag),
+            Event::End(tag) => builder.end(tag),
+            Event::Text(text) => match &mut builder.code {
+                Some((_, code)) => code.push_str(&text),
+                None => builder.push_text(&text, false),
+            },
+            Event::Code(code) => builder.push_text(&code, true),
+            Event::SoftBreak => builder.push_text(" ", false),
+            Event::HardBreak => builder.push_text("\n", false),
+            Event::TaskListMarker(checked) => {
+                builder.push_text(if checked { "[x] " } else { "[ ] " }, false)
+            }
+            Event::Rule => {
+                builder.flush();
+                builder.push_block(Block::Rule);
+            }
+            _ => {}
+        }
+    }
+
+    builder.flush();
+    match builder.frames.into_iter().next() {
+        Some(Frame::Blocks(blocks)) => blocks,
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(text: &str) -> Run {
+        Run {
+            text: text.to_string(),
+            ..Run::default()
+        }
+    }
+
+    #[test]
+    fn parses_inline_styles_lists_and_links() {
+        let blocks = parse("# Plan\n\nSome **bold** and *soft* `code` [docs](https://example.com).\n\n- one\n- two\n\n3. three\n");
+        assert_eq!(blocks[0], Block::Heading(1, vec![plain("Plan")]));
+
+        let Block::Paragraph(runs) = &blocks[1] else {
+            panic!("expected a paragraph, got {:?}", blocks[1]);
+        };
+        assert!(runs.iter().any(|run| run.strong && run.text == "bold"));
+        assert!(runs.iter().any(|run| run.emphasis && run.text == "soft"));
+        assert!(runs.iter().any(|run| run.code && run.text == "code"));
+        assert!(runs
+            .iter()
+            .any(|run| run.link.as_deref() == Some("https://example.com") && run.text == "docs"));
+
+        assert_eq!(
+            blocks[2],
+            Block::List {
+                start: None,
+                items: vec![
+                    vec![Block::Paragraph(vec![plain("one")])],
+                    vec![Block::Paragraph(vec![plain("two")])],
+                ],
+            }
+        );
+        assert!(matches!(blocks[3], Block::List { start: Some(3), .. }));
+    }
+
+    #[test]
+    fn parses_code_blocks_and_tables() {
+        let blocks =
+            parse("```rust\nfn main() {}\n\nlet x = 1;\n```\n\n| a | b |\n|:--|--:|\n| 1 | 2 |\n");
+        assert_eq!(
+            blocks[0],
+            Block::Code {
+                language: Some("rust".to_string()),
+                text: "fn main() {}\n\nlet x = 1;".to_string(),
+            }
+        );
+        assert_eq!(
+            blocks[1],
+            Block::Table {
+                alignments: vec![Alignment::Left, Alignment::Right],
+                header: vec![vec![plain("a")], vec![plain("b")]],
+                rows: vec![vec![vec![plain("1")], vec![plain("2")]]],
+            }
+        );
+    }
+
+    #[test]
+    fn headers_become_dividers_and_edits_reparse_only_their_chunk() {
+        let file = "<!--yoink::::2025-04-01 09:00:00::::dev::::one-->\nfirst\n\n```\ncode\n\nstill code\n```\n<!--yoink::::2025-04-02 09:00:00::::dev::::two-->\n- a\n\n  continued\n\nsecond\n";
+        let mut preview = Preview::default();
+        assert_eq!(preview.update(file), 6);
+
+        let blocks: Vec<&Block> = preview.blocks().collect();
+        assert!(matches!(blocks[0], Block::Divider(header) if header.subject == "one"));
+        assert!(matches!(blocks[2], Block::Code { text, .. } if text == "code\n\nstill code"));
+        assert!(matches!(blocks[3], Block::Divider(header) if header.subject == "two"));
+        assert!(matches!(blocks[4], Block::List { items, .. } if items[0].len() == 2));
+        assert_eq!(blocks.len(), 6);
+
+        assert_eq!(preview.update(&file.replace("second", "2nd")), 1);
+        assert_eq!(preview.update(&file.replace("second", "2nd")), 0);
+        let whole: Vec<Block> = parse_chunk(file.lines().next().unwrap(), "")
+            .into_iter()
+            .chain(parse("first\n\n```\ncode\n\nstill code\n```\n"))
+            .collect();
+        assert_eq!(
+            preview.blocks().take(3).cloned().collect::<Vec<Block>>(),
+            whole
+        );
+    }
+
+    #[test]
+    fn chunked_preview_matches_a_whole_parse() {
+        for text in [
+            "[a][r] and [b]\n\n[r]: http://x\n\npara\n\n[b]: http://y\n",
+            "- a\n\n- b\n\n  more b\n\n1. one\n\n2. two\n\nafter\n",
+            "<!--\n\nhidden\n-->\nshown\n\n```\n[r]: http://z\n```\n\n[r]\n",
+        ] {
+            let mut preview = Preview::default();
+            preview.update(text);
+            assert_eq!(
+                preview.blocks().cloned().collect::<Vec<Block>>(),
+                parse(text),
+                "{:?}",
+                text
+            );
+        }
+
+        let mut preview = Preview::default();
+        preview.update("<!--yoink::::2025-04-01 09:00:00::::dev::::one-->\n[docs]\n");
+        assert!(
+            matches!(preview.blocks().nth(1), Some(Block::Paragraph(runs)) if runs[0].link.is_none())
+        );
+        preview.update("<!--yoink::::2025-04-01 09:00:00::::dev::::one-->\n[docs]\n\n[docs]: https://example.com\n");
+        assert!(matches!(
+            preview.blocks().nth(1),
+            Some(Block::Paragraph(runs)) if runs[0].link.as_deref() == Some("https://example.com")
+        ));
+    }
+}
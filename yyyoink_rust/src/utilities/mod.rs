@@ -1,2 +1,12 @@
+#[cfg(feature = "sqlite-cache")]
+pub mod cache;
 pub mod file;
+pub mod highlighter;
+pub mod history;
+pub mod index;
+pub mod markdown;
+pub mod search;
+pub mod settings;
 pub mod tool;
+pub mod trash;
+pub mod watcher;
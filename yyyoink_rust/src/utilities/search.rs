@@ -0,0 +1,413 @@
+use crate::capture::capture_record::{self, CaptureRecord};
+use chrono::NaiveDate;
+
+const TOPIC_KEY: &str = "topic:";
+const SUBJECT_KEY: &str = "subject:";
+const TAG_KEY: &str = "tag:";
+const BEFORE_KEY: &str = "before:";
+const AFTER_KEY: &str = "after:";
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+const SCORE_MATCH: i64 = 16;
+const BONUS_WORD_START: i64 = 8;
+const BONUS_CONSECUTIVE: i64 = 8;
+const PENALTY_GAP_START: i64 = 3;
+const PENALTY_GAP_EXTENSION: i64 = 1;
+
+/// A parsed search box query.
+///
+/// Bare words are fuzzy matched against the subject and topic, `"quoted phrases"` must appear
+/// verbatim (ignoring case), `topic:` and `subject:` must equal the field, `tag:` must be one of
+/// the capture's tags, `before:` keeps captures strictly older than a date and `after:` keeps
+/// captures on or after it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Query {
+    pub terms: Vec<String>,
+    pub phrases: Vec<String>,
+    pub topics: Vec<String>,
+    pub subjects: Vec<String>,
+    pub tags: Vec<String>,
+    pub before: Option<NaiveDate>,
+    pub after: Option<NaiveDate>,
+}
+
+/// How well an item matched, and which characters of each searched field to highlight.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchMatch {
+    pub score: i64,
+    pub fields: Vec<Vec<usize>>,
+}
+
+impl Query {
+    pub fn parse(input: &str) -> Self {
+        let mut query = Query::default();
+
+        for (token, quoted) in tokenize(input) {
+            if quoted {
+                query.phrases.push(token);
+            } else if let Some(topic) = token.strip_prefix(TOPIC_KEY) {
+                query.topics.push(topic.to_string());
+            } else if let Some(subject) = token.strip_prefix(SUBJECT_KEY) {
+                query.subjects.push(subject.to_string());
+            } else if let Some(tag) = token.strip_prefix(TAG_KEY) {
+                query
+                    .tags
+                    .push(capture_record::normalize_tag(tag).unwrap_or(tag.to_string()));
+            } else if let Some(date) = token.strip_prefix(BEFORE_KEY).and_then(parse_date) {
+                query.before = Some(date);
+            } else if let Some(date) = token.strip_prefix(AFTER_KEY).and_then(parse_date) {
+                query.after = Some(date);
+            } else {
+                query.terms.push(token);
+            }
+        }
+
+        query
+    }
+
+    /// The words to look up in capture bodies: every term and the words of every phrase.
+    pub fn body_text(&self) -> String {
+        self.terms
+            .iter()
+            .chain(self.phrases.iter())
+            .cloned()
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    /// Matches a capture on its topic and subject, in that field order.
+    pub fn match_capture(&self, record: &CaptureRecord) -> Option<SearchMatch> {
+        if !self.passes_filters(record) {
+            return None;
+        }
+        self.match_fields(&[&record.header.topic, &record.header.subject])
+    }
+
+    /// Checks the `topic:`, `subject:`, `tag:`, `before:` and `after:` filters only.
+    pub fn passes_filters(&self, record: &CaptureRecord) -> bool {
+        let header = &record.header;
+        if !self.tags.iter().all(|tag| record.tags.contains(tag)) {
+            return false;
+        }
+        if !self
+            .topics
+            .iter()
+            .all(|topic| eq_ignore_case(topic, &header.topic))
+            || !self
+                .subjects
+                .iter()
+                .all(|subject| eq_ignore_case(subject, &header.subject))
+        {
+            return false;
+        }
+
+        if self.before.is_some() || self.after.is_some() {
+            let Some(date) = header.timestamp.get(..10).and_then(parse_date) else {
+                return false;
+            };
+            if self.before.is_some_and(|before| date >= before)
+                || self.after.is_some_and(|after| date < after)
+            {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Matches a topic file on its name. `topic:` is checked against the name without its
+    /// `_` and `.md`; date filters do not apply to files.
+    pub fn match_file(&self, file_name: &str) -> Option<SearchMatch> {
+        let topic = file_name
+            .strip_prefix('_')
+            .and_then(|name| name.strip_suffix(".md"))
+            .unwrap_or(file_name);
+        if !self.topics.iter().all(|t| eq_ignore_case(t, topic))
+            || !self.subjects.is_empty()
+            || !self.tags.is_empty()
+        {
+            return None;
+        }
+
+        self.match_fields(&[file_name])
+    }
+
+    /// Every term and phrase has to match at least one field. A term counts toward the field
+    /// where it scores best.
+    fn match_fields(&self, fields: &[&str]) -> Option<SearchMatch> {
+        let fields: Vec<Vec<char>> = fields.iter().map(|field| field.chars().collect()).collect();
+        let mut result = SearchMatch {
+            score: 0,
+            fields: vec![Vec::new(); fields.len()],
+        };
+
+        for phrase in &self.phrases {
+            let phrase: Vec<char> = phrase.chars().collect();
+            let (field, start) = fields
+                .iter()
+                .enumerate()
+                .find_map(|(i, field)| find_phrase(field, &phrase).map(|start| (i, start)))?;
+            result.score += 10 * phrase.len() as i64;
+            result.fields[field].extend(start..start + phrase.len());
+        }
+
+        for term in &self.terms {
+            let term: Vec<char> = term.chars().collect();
+            let (field, (score, positions)) = fields
+                .iter()
+                .enumerate()
+                .filter_map(|(i, field)| fuzzy_match(field, &term).map(|found| (i, found)))
+                .max_by_key(|(_, (score, _))| *score)?;
+            result.score += score;
+            result.fields[field].extend(positions);
+        }
+
+        for positions in &mut result.fields {
+            positions.sort_unstable();
+            positions.dedup();
+        }
+        Some(result)
+    }
+}
+
+/// Ranks `items` against `query`, best match first. Ties keep their original order.
+pub fn rank<T>(
+    items: impl Iterator<Item = T>,
+    matcher: impl Fn(&T) -> Option<SearchMatch>,
+) -> Vec<(T, SearchMatch)> {
+    let mut results: Vec<(T, SearchMatch)> = items
+        .filter_map(|item| matcher(&item).map(|found| (item, found)))
+        .collect();
+    results.sort_by_key(|(_, found)| std::cmp::Reverse(found.score));
+    results
+}
+
+/// Splits on whitespace, keeping `"quoted phrases"` and `key:"quoted values"` together.
+/// The flag is set for tokens that are a phrase on their own.
+fn tokenize(input: &str) -> Vec<(String, bool)> {
+    let mut tokens: Vec<(String, bool)> = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut in_quotes = false;
+
+    for c in input.chars() {
+        match c {
+            '"' => {
+                if !in_quotes && current.is_empty() {
+                    quoted = true;
+                }
+                in_quotes = !in_quotes;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() {
+                    tokens.push((std::mem::take(&mut current), quoted));
+                }
+                quoted = false;
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push((current, quoted));
+    }
+
+    tokens
+}
+
+fn parse_date(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value, DATE_FORMAT).ok()
+}
+
+fn chars_eq(a: char, b: char) -> bool {
+    a == b || a.to_lowercase().eq(b.to_lowercase())
+}
+
+fn eq_ignore_case(a: &str, b: &str) -> bool {
+    a.chars().count() == b.chars().count() && a.chars().zip(b.chars()).all(|(a, b)| chars_eq(a, b))
+}
+
+fn is_word_start(field: &[char], index: usize) -> bool {
+    index == 0 || !field[index - 1].is_alphanumeric()
+}
+
+fn find_phrase(field: &[char], phrase: &[char]) -> Option<usize> {
+    if phrase.is_empty() || phrase.len() > field.len() {
+        return None;
+    }
+    (0..=field.len() - phrase.len()).find(|&start| {
+        phrase
+            .iter()
+            .enumerate()
+            .all(|(i, &c)| chars_eq(field[start + i], c))
+    })
+}
+
+/// Matches `term` as a subsequence of `field`, picking the best scoring alignment. Matches at
+/// the start of a word and runs of consecutive characters score higher and gaps cost, so `sp`
+/// ranks `standup plan` above `inspect`.
+fn fuzzy_match(field: &[char], term: &[char]) -> Option<(i64, Vec<usize>)> {
+    if term.is_empty() {
+        return None;
+    }
+
+    // best[i][j]: best score with term[i] matched at field[j], plus where term[i - 1] matched.
+    let mut best: Vec<Vec<Option<(i64, usize)>>> = vec![vec![None; field.len()]; term.len()];
+    for (i, &c) in term.iter().enumerate() {
+        for j in (i..field.len()).filter(|&j| chars_eq(field[j], c)) {
+            let bonus = if is_word_start(field, j) {
+                BONUS_WORD_START
+            } else {
+                0
+            };
+            if i == 0 {
+                best[i][j] = Some((SCORE_MATCH + 2 * bonus, 0));
+                continue;
+            }
+            for k in 0..j {
+                let Some((previous, _)) = best[i - 1][k] else {
+                    continue;
+                };
+                let step = if k + 1 == j {
+                    bonus.max(BONUS_CONSECUTIVE)
+                } else {
+                    bonus - PENALTY_GAP_START - PENALTY_GAP_EXTENSION * (j - k - 2) as i64
+                };
+                let score = previous + SCORE_MATCH + step;
+                if best[i][j].is_none_or(|(current, _)| score > current) {
+                    best[i][j] = Some((score, k));
+                }
+            }
+        }
+    }
+
+    let last = term.len() - 1;
+    let (mut j, score) = best[last]
+        .iter()
+        .enumerate()
+        .filter_map(|(j, cell)| cell.map(|(score, _)| (j, score)))
+        .fold(
+            None,
+            |found: Option<(usize, i64)>, (j, score)| match found {
+                Some((_, best_score)) if best_score >= score => found,
+                _ => Some((j, score)),
+            },
+        )?;
+
+    let mut positions = vec![0; term.len()];
+    for i in (0..term.len()).rev() {
+        positions[i] = j;
+        if let Some((_, previous)) = best[i][j] {
+            j = previous;
+        }
+    }
+    Some((score, positions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(timestamp: &str, topic: &str, subject: &str) -> CaptureRecord {
+        CaptureRecord {
+            header: capture_record::CaptureHeader {
+                timestamp: timestamp.to_string(),
+                topic: topic.to_string(),
+                subject: subject.to_string(),
+                id: None,
+                tags: Vec::new(),
+            },
+            file_name: format!("_{}.md", topic),
+            tags: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn parses_filters_phrases_and_terms() {
+        let query = Query::parse(
+            r#"topic:work "release notes" before:2025-05-01 after:2025-04-01 subject:"q2 plan" draft before:someday"#,
+        );
+        assert_eq!(query.topics, ["work"]);
+        assert_eq!(query.subjects, ["q2 plan"]);
+        assert_eq!(query.phrases, ["release notes"]);
+        assert_eq!(query.terms, ["draft", "before:someday"]);
+        assert_eq!(query.before, NaiveDate::from_ymd_opt(2025, 5, 1));
+        assert_eq!(query.after, NaiveDate::from_ymd_opt(2025, 4, 1));
+        assert_eq!(Query::parse("   "), Query::default());
+    }
+
+    #[test]
+    fn filters_by_topic_and_date() {
+        let query = Query::parse("topic:Work before:2025-05-01 after:2025-04-01");
+        assert!(query
+            .match_capture(&header("2025-04-01 08:00:00", "work", "standup"))
+            .is_some());
+        assert!(query
+            .match_capture(&header("2025-05-01 08:00:00", "work", "standup"))
+            .is_none());
+        assert!(query
+            .match_capture(&header("2025-03-31 23:59:59", "work", "standup"))
+            .is_none());
+        assert!(query
+            .match_capture(&header("2025-04-10 08:00:00", "homework", "standup"))
+            .is_none());
+    }
+
+    #[test]
+    fn filters_by_tag() {
+        let query = Query::parse("tag:#Rust");
+        assert_eq!(query.tags, ["rust"]);
+
+        let mut tagged = header("2025-04-01 08:00:00", "dev", "parser");
+        assert!(query.match_capture(&tagged).is_none());
+        tagged.tags = vec!["ideas".to_string(), "rust".to_string()];
+        assert!(query.match_capture(&tagged).is_some());
+        assert!(query.match_file("_dev.md").is_none());
+    }
+
+    #[test]
+    fn ranks_word_starts_and_runs_first_and_highlights_them() {
+        let headers = [
+            header("2025-04-01 08:00:00", "dev", "inspect logs"),
+            header("2025-04-01 08:00:00", "work", "standup plan"),
+            header("2025-04-01 08:00:00", "home", "groceries"),
+        ];
+        let query = Query::parse("stup");
+        let results = rank(headers.iter(), |h| query.match_capture(h));
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0.header.subject, "standup plan");
+        assert_eq!(results[0].1.fields, [vec![], vec![0, 1, 5, 6]]);
+
+        let query = Query::parse("sp");
+        let results = rank(headers.iter(), |h| query.match_capture(h));
+        assert_eq!(results[0].0.header.subject, "standup plan");
+        assert_eq!(results[1].0.header.subject, "inspect logs");
+    }
+
+    #[test]
+    fn phrases_must_match_verbatim() {
+        let query = Query::parse(r#""Release Notes""#);
+        let found = query
+            .match_capture(&header(
+                "2025-04-01 08:00:00",
+                "work",
+                "draft release notes",
+            ))
+            .unwrap();
+        assert_eq!(found.fields[1], (6..19).collect::<Vec<usize>>());
+        assert!(query
+            .match_capture(&header("2025-04-01 08:00:00", "work", "release the notes"))
+            .is_none());
+    }
+
+    #[test]
+    fn matches_files_by_name_and_topic() {
+        let query = Query::parse("topic:work");
+        assert!(query.match_file("_work.md").is_some());
+        assert!(query.match_file("_home.md").is_none());
+        assert_eq!(
+            Query::parse("wk").match_file("_work.md").unwrap().fields,
+            [vec![1, 4]]
+        );
+    }
+}
@@ -0,0 +1,207 @@
+use crate::capture::capture_record::AssumedZone;
+use std::env;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+const APP_DIR: &str = "yyyoink";
+const CONFIG_FILE: &str = "config";
+const NOTES_DIR_KEY: &str = "notes_dir";
+const NOTES_DIR_ENV: &str = "YYYOINK_NOTES_DIR";
+const NOTES_DIR_ARG: &str = "--notes-dir";
+const REBUILD_CACHE_ARG: &str = "--rebuild-cache";
+const MIGRATE_TIMESTAMPS_ARG: &str = "--migrate-timestamps";
+const VAULT_KEY_PREFIX: &str = "vault.";
+const DEFAULT_VAULT: &str = "default";
+const TRASH_RETENTION_KEY: &str = "trash_retention_days";
+const DEFAULT_TRASH_RETENTION_DAYS: u32 = 30;
+const LEGACY_TIMEZONE_KEY: &str = "legacy_timezone";
+
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
+
+/// A named notes root. Captures and topic files never cross vault boundaries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vault {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+impl fmt::Display for Vault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub vaults: Vec<Vault>,
+    pub trash_retention_days: u32,
+    pub legacy_timezone: AssumedZone,
+}
+
+impl Settings {
+    /// Resolves the default notes root from, in priority order, the `notes_dir` key of
+    /// `$XDG_CONFIG_HOME/yyyoink/config`, `$YYYOINK_NOTES_DIR`, then `--notes-dir <path>`.
+    /// Further vaults come from `vault.<name> = <path>` keys in the same file.
+    /// Falls back to `~/yyyoink` when no root is configured at all.
+    /// `trash_retention_days` sets how long deleted items are kept, 30 days by default.
+    /// `legacy_timezone` is the zone timestamps without an offset are read in, when merging
+    /// topics and migrating: `local`, the default, `utc` or an offset such as `+02:00`.
+    pub fn load() -> Self {
+        let config = read_config();
+        let notes_dir = config
+            .iter()
+            .find(|(key, _)| key == NOTES_DIR_KEY)
+            .map(|(_, value)| expand_home(value))
+            .or_else(|| {
+                env::var(NOTES_DIR_ENV)
+                    .ok()
+                    .map(|value| expand_home(&value))
+            })
+            .or_else(|| notes_dir_arg(env::args().skip(1)).map(|value| expand_home(&value)));
+
+        let mut vaults: Vec<Vault> = Vec::new();
+        if let Some(path) = notes_dir {
+            vaults.push(Vault {
+                name: DEFAULT_VAULT.to_string(),
+                path,
+            });
+        }
+        for (key, value) in &config {
+            if let Some(name) = key.strip_prefix(VAULT_KEY_PREFIX) {
+                if !name.is_empty() && !vaults.iter().any(|vault| vault.name == name) {
+                    vaults.push(Vault {
+                        name: name.to_string(),
+                        path: expand_home(value),
+                    });
+                }
+            }
+        }
+        if vaults.is_empty() {
+            vaults.push(Vault {
+                name: DEFAULT_VAULT.to_string(),
+                path: home_dir().join(APP_DIR),
+            });
+        }
+
+        let trash_retention_days = config
+            .iter()
+            .find(|(key, _)| key == TRASH_RETENTION_KEY)
+            .and_then(|(_, value)| value.parse().ok())
+            .unwrap_or(DEFAULT_TRASH_RETENTION_DAYS);
+        let legacy_timezone = config
+            .iter()
+            .find(|(key, _)| key == LEGACY_TIMEZONE_KEY)
+            .and_then(|(_, value)| {
+                let zone = AssumedZone::parse(value);
+                if zone.is_none() {
+                    eprintln!(
+                        "Ignoring {} = {}, not a timezone.",
+                        LEGACY_TIMEZONE_KEY, value
+                    );
+                }
+                zone
+            })
+            .unwrap_or_default();
+
+        Self {
+            vaults,
+            trash_retention_days,
+            legacy_timezone,
+        }
+    }
+
+    /// The vault opened at startup.
+    pub fn default_vault(&self) -> &Vault {
+        &self.vaults[0]
+    }
+}
+
+/// Loads settings once and creates every vault root that does not exist yet.
+pub fn init() -> &'static Settings {
+    SETTINGS.get_or_init(|| {
+        let settings = Settings::load();
+        for vault in &settings.vaults {
+            if let Err(e) = std::fs::create_dir_all(&vault.path) {
+                eprintln!(
+                    "Failed to create vault {} at {}.\nUnderlying error: {}",
+                    vault.name,
+                    vault.path.display(),
+                    e
+                );
+            }
+        }
+        settings
+    })
+}
+
+pub fn get() -> &'static Settings {
+    init()
+}
+
+/// Whether the app was started with `--rebuild-cache`.
+pub fn rebuild_cache_requested() -> bool {
+    env::args().skip(1).any(|arg| arg == REBUILD_CACHE_ARG)
+}
+
+/// Whether the app was started with `--migrate-timestamps`.
+pub fn migrate_timestamps_requested() -> bool {
+    env::args().skip(1).any(|arg| arg == MIGRATE_TIMESTAMPS_ARG)
+}
+
+fn config_path() -> PathBuf {
+    let config_home = env::var("XDG_CONFIG_HOME")
+        .ok()
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home_dir().join(".config"));
+    config_home.join(APP_DIR).join(CONFIG_FILE)
+}
+
+/// Reads `key = value` lines from the config file in order. Blank lines and `#` comments are
+/// skipped.
+fn read_config() -> Vec<(String, String)> {
+    let mut config = Vec::new();
+    let Ok(content) = std::fs::read_to_string(config_path()) else {
+        return config;
+    };
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            let value = value.trim().trim_matches('"');
+            config.push((key.trim().to_string(), value.to_string()));
+        }
+    }
+
+    config
+}
+
+fn notes_dir_arg(mut args: impl Iterator<Item = String>) -> Option<String> {
+    while let Some(arg) = args.next() {
+        if arg == NOTES_DIR_ARG {
+            return args.next();
+        }
+        if let Some(value) = arg.strip_prefix(&format!("{}=", NOTES_DIR_ARG)) {
+            return Some(value.to_string());
+        }
+    }
+    None
+}
+
+fn home_dir() -> PathBuf {
+    env::var("HOME")
+        .or_else(|_| env::var("USERPROFILE"))
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("."))
+}
+
+fn expand_home(value: &str) -> PathBuf {
+    match value.strip_prefix("~/") {
+        Some(rest) => home_dir().join(rest),
+        None => PathBuf::from(value),
+    }
+}
@@ -1,10 +1,67 @@
-pub fn source_dir() -> String {
-    const TEST_DIR: &str = "/Users/tymalik/Docs/Git/markdown/";
-    return format!("{}", TEST_DIR);
+use super::trash;
+use crate::enums::error::Error;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+
+/// Schemes a preview link may use. Anything else, e.g. `file:`, is not opened.
+const LINK_SCHEMES: [&str; 3] = ["http://", "https://", "mailto:"];
+
+pub fn source_path(root: &Path, capture_file: String) -> PathBuf {
+    root.join(capture_file)
+}
+
+/// Resolves a file of the vault, refusing anything that is not a plain file name directly in
+/// `root` or in its trash, and existing files or links that resolve outside of it.
+pub fn vault_path(root: &Path, file_name: &str) -> Result<PathBuf, Error> {
+    let mut components = Path::new(file_name).components();
+    let path = match (components.next(), components.next(), components.next()) {
+        (Some(Component::Normal(name)), None, _) => root.join(name),
+        (Some(Component::Normal(dir)), Some(Component::Normal(name)), None)
+            if dir == trash::TRASH_DIR =>
+        {
+            root.join(dir).join(name)
+        }
+        _ => {
+            eprintln!("Refusing path {:?}, it is not inside the vault.", file_name);
+            return Err(Error::OutsideVault);
+        }
+    };
+
+    if path.symlink_metadata().is_ok() {
+        let resolved = path.canonicalize().map_err(|e| Error::IoError(e.kind()))?;
+        let root = root.canonicalize().map_err(|e| Error::IoError(e.kind()))?;
+        if !resolved.starts_with(&root) {
+            eprintln!(
+                "Refusing {}, it resolves outside the vault to {}.",
+                path.display(),
+                resolved.display()
+            );
+            return Err(Error::OutsideVault);
+        }
+    }
+
+    Ok(path)
 }
 
-pub fn source_path(capture_file: String) -> String {
-    let source_dir = source_dir();
-    let test_dir: &str = source_dir.as_ref();
-    return format!("{}{}", test_dir, capture_file);
+/// Opens a link from the markdown preview with the system's default handler.
+pub fn open_link(url: &str) {
+    if !LINK_SCHEMES.iter().any(|scheme| url.starts_with(scheme)) {
+        eprintln!("Not opening link {}, unsupported scheme.", url);
+        return;
+    }
+
+    #[cfg(target_os = "macos")]
+    let mut command = Command::new("open");
+    #[cfg(target_os = "windows")]
+    let mut command = {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    };
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    let mut command = Command::new("xdg-open");
+
+    if let Err(e) = command.arg(url).spawn() {
+        eprintln!("Failed to open link {}.\nUnderlying error: {}", url, e);
+    }
 }
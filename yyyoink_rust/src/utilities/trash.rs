@@ -0,0 +1,347 @@
+use super::file;
+use super::tool;
+use crate::capture::capture_record::CaptureHeader;
+use crate::enums::error::Error;
+use chrono::prelude::*;
+use std::fmt;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use ulid::Ulid;
+
+pub const TRASH_DIR: &str = ".trash";
+const TRASH_PREFIX: &str = "<!--yoink-trash";
+const TRASH_DELIMITER: &str = "::::";
+const TRASH_SUFFIX: &str = "-->";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrashKind {
+    Capture,
+    File,
+}
+
+impl TrashKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            TrashKind::Capture => "capture",
+            TrashKind::File => "file",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "capture" => Some(TrashKind::Capture),
+            "file" => Some(TrashKind::File),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TrashKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// A removed capture section or topic file, stored under `<vault>/.trash/<id>.md`.
+/// The first line of the trash file records where it came from and when it was deleted,
+/// the rest is the removed content verbatim.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrashItem {
+    pub id: String,
+    pub kind: TrashKind,
+    pub original_file: String,
+    pub deleted_at: DateTime<FixedOffset>,
+    pub content: String,
+}
+
+impl TrashItem {
+    /// A one-line description for the trash list.
+    pub fn summary(&self) -> String {
+        match self.kind {
+            TrashKind::Capture => self
+                .content
+                .lines()
+                .next()
+                .and_then(CaptureHeader::parse)
+                .map(|header| header.subject)
+                .unwrap_or_default(),
+            TrashKind::File => format!("{} lines", self.content.lines().count()),
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        format!(
+            "{}{}{}{}{}{}{}{}\n{}",
+            TRASH_PREFIX,
+            TRASH_DELIMITER,
+            self.kind,
+            TRASH_DELIMITER,
+            self.original_file,
+            TRASH_DELIMITER,
+            self.deleted_at.to_rfc3339(),
+            TRASH_SUFFIX,
+            self.content
+        )
+    }
+
+    fn parse(id: &str, text: &str) -> Option<Self> {
+        let (meta, content) = text.split_once('\n')?;
+        let inner = meta
+            .strip_prefix(TRASH_PREFIX)?
+            .strip_suffix(TRASH_SUFFIX)?
+            .strip_prefix(TRASH_DELIMITER)?;
+        let mut parts = inner.split(TRASH_DELIMITER);
+
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(kind), Some(original_file), Some(deleted_at), None) => Some(Self {
+                id: id.to_string(),
+                kind: TrashKind::parse(kind)?,
+                original_file: original_file.to_string(),
+                deleted_at: DateTime::parse_from_rfc3339(deleted_at).ok()?,
+                content: content.to_string(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+fn trash_dir(root: &Path) -> PathBuf {
+    root.join(TRASH_DIR)
+}
+
+fn trash_path(root: &Path, id: &str) -> PathBuf {
+    root.join(trash_file(id))
+}
+
+/// The trash entry's path relative to the vault root, e.g. `.trash/01J….md`.
+pub fn trash_file(id: &str) -> String {
+    format!("{}/{}.md", TRASH_DIR, id)
+}
+
+/// Moves `content`, removed from `original_file`, into the vault's trash.
+pub async fn trash(
+    root: &Path,
+    kind: TrashKind,
+    original_file: &str,
+    content: &str,
+) -> Result<TrashItem, Error> {
+    let item = TrashItem {
+        id: Ulid::new().to_string(),
+        kind,
+        original_file: original_file.to_string(),
+        deleted_at: Local::now().fixed_offset(),
+        content: content.to_string(),
+    };
+
+    fs::create_dir_all(trash_dir(root))
+        .await
+        .map_err(|e| Error::IoError(e.kind()))?;
+    file::write_atomic(&trash_path(root, &item.id), item.to_text().as_bytes())
+        .await
+        .map_err(|e| {
+            eprintln!(
+                "Failed to write trash item {}.\nUnderlying error: {}",
+                item.id, e
+            );
+            Error::IoError(e.kind())
+        })?;
+
+    Ok(item)
+}
+
+/// Drops an entry again, used when the delete that trashed it did not go through.
+pub async fn discard(root: &Path, item: &TrashItem) {
+    if let Err(e) = fs::remove_file(trash_path(root, &item.id)).await {
+        eprintln!(
+            "Failed to discard trash item {}.\nUnderlying error: {}",
+            item.id, e
+        );
+    }
+}
+
+/// Lists the vault's trash, most recently deleted first.
+pub async fn load_trash(root: PathBuf) -> Result<Vec<TrashItem>, Error> {
+    let mut items: Vec<TrashItem> = Vec::new();
+    let mut entries = match fs::read_dir(trash_dir(&root)).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(items),
+        Err(e) => return Err(Error::IoError(e.kind())),
+    };
+
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .map_err(|e| Error::IoError(e.kind()))?
+    {
+        let path = entry.path();
+        let Some(id) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".md"))
+        else {
+            continue;
+        };
+        match fs::read_to_string(&path).await {
+            Ok(text) => match TrashItem::parse(id, &text) {
+                Some(item) => items.push(item),
+                None => eprintln!("Skipping unreadable trash item {}.", path.display()),
+            },
+            Err(e) => eprintln!("Failed to read trash item {}: {}", path.display(), e),
+        }
+    }
+
+    items.sort_by_key(|item| std::cmp::Reverse(item.deleted_at));
+    Ok(items)
+}
+
+/// Puts a trashed capture back at the end of its topic file, or recreates a trashed file.
+/// A file is never restored over one that has been created since.
+pub async fn restore(root: PathBuf, item: TrashItem) -> Result<bool, Error> {
+    match item.kind {
+        TrashKind::Capture => {
+            file::append_file(
+                root.clone(),
+                item.original_file.clone(),
+                item.content.clone(),
+            )
+            .await?;
+        }
+        TrashKind::File => {
+            let path = tool::vault_path(&root, &item.original_file)?;
+            if fs::metadata(&path).await.is_ok() {
+                eprintln!("Refusing to restore over existing {}.", path.display());
+                return Err(Error::IoError(ErrorKind::AlreadyExists));
+            }
+            file::write_file(
+                root.clone(),
+                item.original_file.clone(),
+                item.content.clone(),
+            )
+            .await?;
+        }
+    }
+
+    fs::remove_file(trash_path(&root, &item.id))
+        .await
+        .map_err(|e| Error::IoError(e.kind()))?;
+    Ok(true)
+}
+
+/// Removes trash entries older than `retention_days`, returning how many were purged.
+pub async fn purge_expired(root: PathBuf, retention_days: u32) -> Result<usize, Error> {
+    let cutoff = Local::now().fixed_offset() - chrono::Duration::days(retention_days as i64);
+    let mut purged = 0;
+
+    for item in load_trash(root.clone()).await? {
+        if item.deleted_at < cutoff {
+            fs::remove_file(trash_path(&root, &item.id))
+                .await
+                .map_err(|e| Error::IoError(e.kind()))?;
+            purged += 1;
+        }
+    }
+
+    Ok(purged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::capture_record::CaptureRecord;
+
+    const TOPIC: &str =
+        "<!--yoink::::2025-04-01 09:00:00::::work::::standup::::id=01JQTRASH000000000000000001-->
+kept
+<!--yoink::::2025-04-02 09:00:00::::work::::retro::::id=01JQTRASH000000000000000002-->
+\tremoved
+";
+
+    async fn vault() -> PathBuf {
+        let root = std::env::temp_dir().join(format!("yyyoink-trash-{}", Ulid::new()));
+        fs::create_dir_all(&root).await.unwrap();
+        fs::write(root.join("_work.md"), TOPIC).await.unwrap();
+        root
+    }
+
+    #[tokio::test]
+    async fn deleted_capture_can_be_restored() {
+        let root = vault().await;
+        let record = file::load_captures(root.clone())
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|record: &CaptureRecord| record.header.subject == "retro")
+            .unwrap();
+
+        file::delete_capture(root.clone(), record.clone())
+            .await
+            .unwrap();
+        let items = load_trash(root.clone()).await.unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].kind, TrashKind::Capture);
+        assert_eq!(items[0].original_file, "_work.md");
+        assert_eq!(items[0].summary(), "retro");
+
+        restore(root.clone(), items[0].clone()).await.unwrap();
+        let captures = file::load_captures(root.clone()).await.unwrap();
+        assert!(captures
+            .iter()
+            .any(|r| r.header.is_same_capture(&record.header)));
+        let topic = fs::read_to_string(root.join("_work.md")).await.unwrap();
+        assert!(topic.contains("\tremoved\n"));
+        assert!(load_trash(root.clone()).await.unwrap().is_empty());
+
+        fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn deleted_file_is_not_restored_over_a_new_one() {
+        let root = vault().await;
+
+        file::delete_file(root.clone(), "_work.md".to_string())
+            .await
+            .unwrap();
+        assert!(fs::metadata(root.join("_work.md")).await.is_err());
+        let item = load_trash(root.clone()).await.unwrap().remove(0);
+        assert_eq!(item.kind, TrashKind::File);
+        assert_eq!(item.content, TOPIC);
+
+        fs::write(root.join("_work.md"), "new").await.unwrap();
+        assert!(restore(root.clone(), item.clone()).await.is_err());
+
+        fs::remove_file(root.join("_work.md")).await.unwrap();
+        restore(root.clone(), item).await.unwrap();
+        assert_eq!(
+            fs::read_to_string(root.join("_work.md")).await.unwrap(),
+            TOPIC
+        );
+
+        fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn purge_expired_only_removes_old_items() {
+        let root = vault().await;
+        let old = trash(&root, TrashKind::File, "_old.md", "old\n")
+            .await
+            .unwrap();
+        let expired = TrashItem {
+            deleted_at: old.deleted_at - chrono::Duration::days(31),
+            ..old
+        };
+        fs::write(trash_path(&root, &expired.id), expired.to_text())
+            .await
+            .unwrap();
+        trash(&root, TrashKind::File, "_new.md", "new\n")
+            .await
+            .unwrap();
+
+        assert_eq!(purge_expired(root.clone(), 30).await.unwrap(), 1);
+        let items = load_trash(root.clone()).await.unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].original_file, "_new.md");
+
+        fs::remove_dir_all(&root).await.unwrap();
+    }
+}
@@ -0,0 +1,80 @@
+use super::settings::Vault;
+use crate::enums::message::Message;
+use iced::futures::SinkExt;
+use iced::Subscription;
+use notify::{RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// How long to wait for more events before reporting a batch, so one save that touches a file
+/// several times is re-indexed once.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Watches the vault root and reports the topic files that were created, changed or removed.
+pub fn watch(vault: Vault) -> Subscription<Message> {
+    let id = (vault.name.clone(), vault.path.clone());
+
+    Subscription::run_with_id(
+        id,
+        iced::stream::channel(100, move |mut output| async move {
+            let (sender, mut receiver) = mpsc::unbounded_channel();
+            let mut watcher =
+                match notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                    let _ = sender.send(event);
+                }) {
+                    Ok(watcher) => watcher,
+                    Err(e) => {
+                        eprintln!("Failed to start file watcher.\nUnderlying error: {}", e);
+                        return;
+                    }
+                };
+            if let Err(e) = watcher.watch(&vault.path, RecursiveMode::NonRecursive) {
+                eprintln!(
+                    "Failed to watch {}.\nUnderlying error: {}",
+                    vault.path.display(),
+                    e
+                );
+                return;
+            }
+
+            while let Some(event) = receiver.recv().await {
+                let mut changed: BTreeSet<String> = BTreeSet::new();
+                collect_topic_files(event, &mut changed);
+                while let Ok(Some(event)) = tokio::time::timeout(DEBOUNCE, receiver.recv()).await {
+                    collect_topic_files(event, &mut changed);
+                }
+
+                if !changed.is_empty() {
+                    let message =
+                        Message::TopicsChanged(vault.name.clone(), changed.into_iter().collect());
+                    if output.send(message).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }),
+    )
+}
+
+/// Adds the `_topic.md` files an event touched. Temp files and the trash are skipped.
+fn collect_topic_files(event: notify::Result<notify::Event>, changed: &mut BTreeSet<String>) {
+    let event = match event {
+        Ok(event) => event,
+        Err(e) => {
+            eprintln!("File watcher error: {}", e);
+            return;
+        }
+    };
+    if event.kind.is_access() {
+        return;
+    }
+
+    for path in event.paths {
+        if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+            if name.starts_with('_') && name.ends_with(".md") {
+                changed.insert(name.to_string());
+            }
+        }
+    }
+}
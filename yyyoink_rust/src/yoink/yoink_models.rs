@@ -1,25 +1,38 @@
+use std::future::Future;
+use std::path::PathBuf;
 use std::time::Instant;
 
 use iced::advanced::widget::operation::{focusable, Focusable};
 use iced::border::{self, Radius};
 use iced::widget::{
-    button, center, column as col, container, mouse_area, opaque, pane_grid, row, scrollable,
-    stack, text, text_editor, text_input,
+    button, center, column as col, container, horizontal_rule, mouse_area, opaque, pane_grid,
+    pick_list, rich_text, row, scrollable, span, stack, text, text_editor, text_input,
 };
 use iced::Length::Shrink;
 use iced::{
-    mouse, overlay, touch, Background, Border, Color, Element, Length, Padding, Rectangle, Shadow,
-    Size, Theme, Vector,
+    font, mouse, overlay, touch, Background, Border, Color, Element, Font, Length, Padding,
+    Rectangle, Shadow, Size, Task, Theme, Vector,
 };
 
 use crate::capture::capture_models::Capture;
 use crate::capture::capture_pane::CapturePane;
+use crate::capture::capture_record::{self, CaptureRecord, Collision, TopicFile};
 use crate::capture::capture_sidebar::CaptureSidebar;
-use crate::editor::editor_models::Editor;
+use crate::capture::topic_name::TopicName;
+use crate::editor::editor_models::{Editor, PreviewMode};
 use crate::editor::editor_pane::EditorPane;
 use crate::editor::editor_sidebar::EditorSidebar;
+use crate::enums::error::Error;
 use crate::enums::message::Message;
 use crate::enums::pane::PaneState;
+use crate::utilities::file;
+use crate::utilities::highlighter::{self, Highlighter};
+use crate::utilities::history::{History, HistoryEntry};
+use crate::utilities::index;
+use crate::utilities::markdown::{Block, Run};
+use crate::utilities::search::{self, Query, SearchMatch};
+use crate::utilities::settings::{self, Vault};
+use crate::utilities::trash::{self, TrashItem};
 use iced::advanced::layout::{self, Layout};
 use iced::advanced::widget::tree::{self, Tree};
 use iced::advanced::widget::{operation, Id, Operation, Widget};
@@ -27,18 +40,27 @@ use iced::advanced::{renderer, Clipboard, Shell};
 use iced::event::{self, Event};
 use iced::keyboard;
 use iced::theme::palette;
+use pulldown_cmark::Alignment;
+
+/// How many full-text matches the capture sidebar lists.
+const BODY_RESULTS: usize = 50;
+/// How many existing tags the capture form offers for the tag being typed.
+const TAG_SUGGESTIONS: usize = 5;
 
 pub struct Yoink {
     pub is_capture: bool,
     pub editor: Editor,
-    pub captures: Vec<Vec<String>>,
+    pub captures: Vec<CaptureRecord>,
     pub capture: Capture,
     pub capture_pane: CapturePane,
     pub capture_sidebar: CaptureSidebar,
     pub files: Vec<String>,
     pub editor_pane: EditorPane,
     pub editor_sidebar: EditorSidebar,
-    pub opened_file: Vec<String>,
+    /// The topic file open in whole-file mode, if any.
+    pub opened_file: Option<String>,
+    /// The topic file whose preamble, the text before its first capture, is being edited.
+    pub opened_preamble: Option<String>,
     pub ui_error: String,
     pub show_helper: bool,
     pub panes: pane_grid::State<PaneState>,
@@ -47,6 +69,23 @@ pub struct Yoink {
     pub is_subselect_capture: bool,
     pub newfile_submit_enabled: bool,
     pub modal_helper: bool,
+    pub show_trash: bool,
+    pub trash: Vec<TrashItem>,
+    pub show_tags: bool,
+    /// The tag picked in the tag view, and the name it is being renamed or merged to.
+    pub tag_rename_from: Option<String>,
+    pub tag_rename_to: String,
+    pub show_topics: bool,
+    /// The topic picked in the topic view, and the topic it is being renamed or merged to.
+    pub topic_edit_from: Option<String>,
+    pub topic_edit_to: String,
+    /// The topic file a create was refused for because it exists, offered to open instead.
+    pub existing_topic: Option<String>,
+    /// Collisions reported by the last merge.
+    pub topic_collisions: Vec<Collision>,
+    pub history: History,
+    pub vaults: Vec<Vault>,
+    pub active_vault: Vault,
 }
 
 impl Yoink {
@@ -57,61 +96,633 @@ impl Yoink {
         self.modal_helper = false;
     }
 
-    // fn hide_subselect_capture(&mut self) {
-    //     self.is_subselect_capture = false;
-    // }
+    pub fn vault_root(&self) -> PathBuf {
+        self.active_vault.path.clone()
+    }
 
-    pub fn view_capture_sidebar(&self) -> Element<Message> {
-        let capture_list = self
+    /// Loads captures from the active vault, tagging the result with the vault name so a
+    /// load that finishes after a vault switch can be discarded.
+    pub fn load_captures(
+        &self,
+        on_load: fn(String, Result<Vec<CaptureRecord>, Error>) -> Message,
+    ) -> Task<Message> {
+        let vault = self.active_vault.name.clone();
+        Task::perform(file::load_captures(self.vault_root()), move |result| {
+            on_load(vault.clone(), result)
+        })
+    }
+
+    pub fn load_files(&self) -> Task<Message> {
+        let vault = self.active_vault.name.clone();
+        Task::perform(file::load_files(self.vault_root()), move |result| {
+            Message::FilesLoaded(vault.clone(), result)
+        })
+    }
+
+    /// Purges expired trash entries, then lists what is left in the active vault's trash.
+    pub fn load_trash(&self) -> Task<Message> {
+        let vault = self.active_vault.name.clone();
+        let root = self.vault_root();
+        let retention_days = settings::get().trash_retention_days;
+        Task::perform(
+            async move {
+                if let Err(e) = trash::purge_expired(root.clone(), retention_days).await {
+                    eprintln!("Failed to purge trash.\nUnderlying error: {}", e);
+                }
+                trash::load_trash(root).await
+            },
+            move |result| Message::TrashLoaded(vault.clone(), result),
+        )
+    }
+
+    /// Runs a journaled file operation, handing its undo entry to the history before its result.
+    pub fn perform_recorded<T: Send + 'static>(
+        &self,
+        operation: impl Future<Output = (Result<T, Error>, Option<HistoryEntry>)> + Send + 'static,
+        on_done: fn(Result<T, Error>) -> Message,
+    ) -> Task<Message> {
+        Task::perform(operation, |recorded| recorded).then(move |(result, entry)| {
+            Task::batch([
+                Task::done(Message::Journaled(entry)),
+                Task::done(on_done(result)),
+            ])
+        })
+    }
+
+    /// Re-reads only the topic files the watcher reported, then the file list.
+    pub fn reindex_topics(&self, files: Vec<String>) -> Task<Message> {
+        let vault = self.active_vault.name.clone();
+        Task::batch([
+            Task::perform(file::read_topics(self.vault_root(), files), move |result| {
+                Message::TopicsReindexed(vault.clone(), result)
+            }),
+            self.load_files(),
+        ])
+    }
+
+    /// Swaps the captures of a changed topic file in place, keeping the sidebar order.
+    pub fn replace_topic_captures(&mut self, file_name: &str, content: Option<&str>) {
+        let position = self
             .captures
             .iter()
+            .position(|capture| capture.file_name == file_name)
+            .unwrap_or(self.captures.len());
+        self.captures
+            .retain(|capture| capture.file_name != file_name);
+        let records = content
+            .map(|content| file::capture_records(file_name, content))
+            .unwrap_or_default();
+        self.captures.splice(position..position, records);
+    }
+
+    /// Brings the open capture in line with its topic file after it changed on disk. The other
+    /// sections are always refreshed so a save does not undo them. The editor is only reloaded
+    /// when it has no unsaved edits; otherwise the edits are kept and a conflict is flagged.
+    pub fn sync_opened_capture(&mut self, content: Option<&str>) {
+        let (Some(record), Some(opened)) =
+            (&self.capture.opened_capture, &self.capture.opened_section)
+        else {
+            return;
+        };
+        let topic = content.map(TopicFile::parse).unwrap_or_default();
+
+        match topic.split(&record.header) {
+            Some((before, section, after)) => {
+                self.capture.before = before;
+                self.capture.after = after;
+                if section.text == opened.text {
+                    return;
+                }
+                if self.editor.is_saved {
+                    let body = section.body().trim_end_matches(['\n', '\r']);
+                    self.editor.editor_content = text_editor::Content::with_text(body);
+                    self.editor.refresh_preview();
+                } else {
+                    self.editor.conflict = Some(format!(
+                        "{} changed on disk. Your edits were kept, saving replaces the version on disk.",
+                        record.header.subject
+                    ));
+                }
+                self.capture.opened_section = Some(section);
+            }
+            None => {
+                self.capture.before = content.unwrap_or_default().to_string();
+                self.capture.after = String::new();
+                self.editor.conflict = Some(format!(
+                    "{} was removed on disk. Saving adds it back.",
+                    record.header.subject
+                ));
+            }
+        }
+    }
+
+    /// Reloads the file open in whole-file mode after it changed on disk, unless it has unsaved
+    /// edits; then the edits are kept and a conflict is flagged.
+    pub fn sync_opened_file(&mut self, content: Option<&str>) {
+        let Some(file) = &self.opened_file else {
+            return;
+        };
+        match content {
+            Some(content) if self.editor.file_text() == content => {}
+            Some(content) if self.editor.is_saved => self.editor.open_file_text(content),
+            Some(_) => {
+                self.editor.conflict = Some(format!(
+                    "{} changed on disk. Your edits were kept, saving replaces the version on disk.",
+                    file
+                ));
+            }
+            None => {
+                self.editor.conflict = Some(format!(
+                    "{} was removed on disk. Saving adds it back.",
+                    file
+                ));
+            }
+        }
+    }
+
+    /// Explains why a typed topic was refused and suggests a safe one, if there is one.
+    pub fn invalid_topic_message(raw: &str, error: Error) -> String {
+        match TopicName::slugify(raw) {
+            Some(slug) => format!("Submission failed: {}. Try \"{}\".", error, slug),
+            None => format!("Submission failed: {}.", error),
+        }
+    }
+
+    /// Reports a failed undo or redo, otherwise reloads what it touched.
+    pub fn history_applied(
+        &mut self,
+        action: String,
+        result: &Result<bool, Error>,
+    ) -> Task<Message> {
+        if let Err(e) = result {
+            self.ui_error = format!("{} failed: {}", action, e);
+            Task::perform(file::log(), Message::ShowError)
+        } else {
+            println!("{}", action);
+            self.load_vault()
+        }
+    }
+
+    pub fn load_vault(&self) -> Task<Message> {
+        Task::batch([
+            self.load_captures(Message::CapturesLoaded),
+            self.load_files(),
+            self.load_trash(),
+        ])
+    }
+
+    pub fn view_trash(&self) -> Element<Message> {
+        let trash_list = self
+            .trash
+            .iter()
             .enumerate()
-            .map(|(i, capture)| {
-                let text_fields = capture
+            .map(|(i, item)| {
+                row![
+                    text(item.kind.to_string()).width(70),
+                    text(item.original_file.clone()).width(150),
+                    text(item.summary()).width(Length::Fill),
+                    text(item.deleted_at.format("%Y-%m-%d %H:%M").to_string()).width(140),
+                    button("Restore").on_press(Message::RestoreTrash(i)),
+                ]
+                .spacing(10)
+                .align_y(iced::Alignment::Center)
+                .into()
+            })
+            .collect::<Vec<Element<Message>>>();
+
+        let trash_list: Element<Message> = if trash_list.is_empty() {
+            text("Trash is empty.").into()
+        } else {
+            scrollable(col(trash_list).spacing(5)).height(400).into()
+        };
+
+        container(
+            col![
+                row![
+                    text("Trash").width(Length::Fill),
+                    button("X").on_press(Message::HideTrash)
+                ]
+                .height(50)
+                .align_y(iced::Alignment::Center),
+                trash_list,
+                text(format!(
+                    "Items are purged after {} days.",
+                    settings::get().trash_retention_days
+                )),
+            ]
+            .spacing(10),
+        )
+        .width(750)
+        .padding(10)
+        .style(|_theme| container::Style {
+            text_color: Some(iced::Color::from_rgb8(255, 224, 181)),
+            background: Some(iced::Background::Color(Color::from_rgb8(25, 19, 19))),
+            border: iced::Border::default(),
+            shadow: iced::Shadow::default(),
+        })
+        .into()
+    }
+
+    /// Captures matching the capture search and tag filter, best first, with their index into
+    /// `captures`.
+    pub fn capture_results(&self) -> Vec<(usize, SearchMatch)> {
+        let query = Query::parse(&self.capture.search);
+        search::rank(0..self.captures.len(), |&i| {
+            let capture = &self.captures[i];
+            match &self.capture.tag_filter {
+                Some(tag) if !capture.tags.contains(tag) => None,
+                _ => query.match_capture(capture),
+            }
+        })
+    }
+
+    /// The `_topic.md` files of the active vault.
+    pub fn topic_files(&self) -> Vec<String> {
+        self.files
+            .iter()
+            .filter(|file| file.starts_with('_') && file.ends_with(".md"))
+            .cloned()
+            .collect()
+    }
+
+    /// Every tag in the vault with the number of captures carrying it, by name.
+    pub fn all_tags(&self) -> Vec<(String, usize)> {
+        let mut counts: std::collections::BTreeMap<&str, usize> = Default::default();
+        for capture in &self.captures {
+            for tag in &capture.tags {
+                *counts.entry(tag).or_default() += 1;
+            }
+        }
+        counts
+            .into_iter()
+            .map(|(tag, count)| (tag.to_string(), count))
+            .collect()
+    }
+
+    /// Existing tags starting with the tag being typed in the capture form, most used first.
+    pub fn tag_suggestions(&self) -> Vec<String> {
+        let form_tags = &self.capture.form_tags;
+        if form_tags.ends_with(|c: char| c == ',' || c.is_whitespace()) {
+            return Vec::new();
+        }
+        let Some(partial) = form_tags
+            .rsplit(|c: char| c == ',' || c.is_whitespace())
+            .next()
+            .and_then(capture_record::normalize_tag)
+        else {
+            return Vec::new();
+        };
+        let entered = capture_record::parse_tags(form_tags);
+
+        let mut tags: Vec<(String, usize)> = self
+            .all_tags()
+            .into_iter()
+            .filter(|(tag, _)| tag.starts_with(&partial) && *tag != partial)
+            .filter(|(tag, _)| !entered.contains(tag))
+            .collect();
+        tags.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        tags.into_iter()
+            .take(TAG_SUGGESTIONS)
+            .map(|(tag, _)| tag)
+            .collect()
+    }
+
+    /// The capture form's tags with the one being typed replaced by `tag`.
+    pub fn complete_tag(&self, tag: &str) -> String {
+        let form_tags = &self.capture.form_tags;
+        let start = form_tags
+            .rfind(|c: char| c == ',' || c.is_whitespace())
+            .map_or(0, |i| i + 1);
+        format!("{}{}, ", &form_tags[..start], tag)
+    }
+
+    pub fn view_tags(&self) -> Element<Message> {
+        let tag_list = self
+            .all_tags()
+            .into_iter()
+            .map(|(tag, count)| {
+                row![
+                    text(tag.clone()).width(Length::Fill),
+                    text(format!("{} captures", count)).width(100),
+                    button("Rename").on_press(Message::TagRenameSelected(tag)),
+                ]
+                .spacing(10)
+                .align_y(iced::Alignment::Center)
+                .into()
+            })
+            .collect::<Vec<Element<Message>>>();
+
+        let tag_list: Element<Message> = if tag_list.is_empty() {
+            text("No tags yet.").into()
+        } else {
+            scrollable(col(tag_list).spacing(5)).height(400).into()
+        };
+
+        let rename: Element<Message> = match &self.tag_rename_from {
+            Some(from) => row![
+                text(format!("Rename {} to", from)),
+                text_input("Tag..", &self.tag_rename_to)
+                    .on_input(Message::TagRenameInput)
+                    .on_submit(Message::RenameTag),
+                button("Apply").on_press(Message::RenameTag),
+            ]
+            .spacing(10)
+            .align_y(iced::Alignment::Center)
+            .into(),
+            None => text("Renaming to an existing tag merges the two.").into(),
+        };
+
+        container(
+            col![
+                row![
+                    text("Tags").width(Length::Fill),
+                    button("X").on_press(Message::HideTags)
+                ]
+                .height(50)
+                .align_y(iced::Alignment::Center),
+                tag_list,
+                rename,
+            ]
+            .spacing(10),
+        )
+        .width(750)
+        .padding(10)
+        .style(|_theme| container::Style {
+            text_color: Some(iced::Color::from_rgb8(255, 224, 181)),
+            background: Some(iced::Background::Color(Color::from_rgb8(25, 19, 19))),
+            border: iced::Border::default(),
+            shadow: iced::Shadow::default(),
+        })
+        .into()
+    }
+
+    /// Follows a renamed or merged topic: reloads the vault and re-opens the opened capture
+    /// under its new topic, unless the merge had to give it a new ID.
+    pub fn topic_changed(&mut self, collisions: &[Collision]) -> Task<Message> {
+        let reload = Task::batch([
+            self.load_captures(Message::CapturesLoaded),
+            self.load_files(),
+        ]);
+        let (Some(from), Some(record)) = (
+            self.topic_edit_from.take(),
+            self.capture.opened_capture.clone(),
+        ) else {
+            return reload;
+        };
+        if record.header.topic != from {
+            return reload;
+        }
+
+        let renumbered = collisions.iter().any(|collision| {
+            matches!(collision, Collision::Id { id, .. } if Some(id) == record.header.id.as_ref())
+        });
+        if renumbered {
+            self.capture.opened_capture = None;
+            self.capture.opened_section = None;
+            self.capture.current_capture = "Editor..".to_string();
+            return reload;
+        }
+
+        let mut record = record;
+        record.header.topic = self.topic_edit_to.trim().to_string();
+        record.file_name = record.header.file_name();
+        Task::batch([reload, Task::done(Message::CaptureOpened(Ok(record)))])
+    }
+
+    pub fn view_topics(&self) -> Element<Message> {
+        let topic_list = self
+            .topic_files()
+            .into_iter()
+            .map(|file| {
+                let count = self
+                    .captures
                     .iter()
-                    .map(|field| {
-                        text_input("", field)
-                            .on_input(|_| Message::Ignore)
-                            .padding(5)
-                            .style(|_theme, _status| text_input::Style {
-                                background: Background::Color(Color::BLACK),
-                                border: Border::default(),
-                                icon: iced::Color::from_rgb8(255, 244, 181),
-                                placeholder: iced::Color::from_rgb8(255, 244, 181),
-                                value: iced::Color::from_rgb8(255, 244, 181),
-                                selection: iced::Color::from_rgb8(255, 244, 181),
-                            })
-                            .into()
-                        // let capture_button = mouse_area(
-                        //     button(row![
-                        //         col(capture
-                        //             .iter()
-                        //             .map(|field| text(field).into())
-                        //             .collect::<Vec<Element<Message>>>()),
-                        //         button("DEL").on_press(Message::DeleteCapture(i))
-                        //     ])
-                        //     .width(750)
-                        //     .on_press(Message::CaptureSelected(i))
-                        //     .style(|_theme, status| match status {
-                        //         button::Status::Hovered => button::Style {
-                        //             background: Some(iced::Background::Color(Color::from_rgb8(25, 19, 19))),
-                        //             text_color: iced::Color::from_rgb8(255, 224, 181),
-                        //             border: iced::Border::default(),
-                        //             shadow: iced::Shadow::default(),
-                        //         },
-                        //         _ => button::Style {
-                        //             background: Some(iced::Background::Color(Color::from_rgb8(15, 9, 9))),
-                        //             text_color: iced::Color::from_rgb8(255, 224, 181),
-                        //             border: iced::Border::default(),
-                        //             shadow: iced::Shadow::default(),
-                        //         },
-                        //     }),
-                        // )
-                        // .on_right_press(Message::SubselectCapture);
-                        //
-                        // capture_button.into()
-                    })
-                    .collect::<Vec<Element<Message>>>();
+                    .filter(|capture| capture.file_name == file)
+                    .count();
+                let topic = file
+                    .strip_prefix('_')
+                    .and_then(|name| name.strip_suffix(".md"))
+                    .unwrap_or(&file)
+                    .to_string();
+                row![
+                    text(topic.clone()).width(Length::Fill),
+                    text(format!("{} captures", count)).width(100),
+                    button("Preamble").on_press(Message::PreambleSelected(file.clone())),
+                    button("Edit").on_press(Message::TopicEditSelected(topic)),
+                ]
+                .spacing(10)
+                .align_y(iced::Alignment::Center)
+                .into()
+            })
+            .collect::<Vec<Element<Message>>>();
+
+        let topic_list: Element<Message> = if topic_list.is_empty() {
+            text("No topics yet.").into()
+        } else {
+            scrollable(col(topic_list).spacing(5)).height(300).into()
+        };
+
+        let edit: Element<Message> = match &self.topic_edit_from {
+            Some(from) => row![
+                text(format!("{} to", from)),
+                text_input("Topic..", &self.topic_edit_to).on_input(Message::TopicEditInput),
+                button("Rename").on_press(Message::RenameTopic),
+                button("Merge").on_press(Message::MergeTopic),
+            ]
+            .spacing(10)
+            .align_y(iced::Alignment::Center)
+            .into(),
+            None => text("Merging folds a topic's captures into another by timestamp.").into(),
+        };
+
+        let collisions = col(self
+            .topic_collisions
+            .iter()
+            .map(|collision| text(collision.to_string()).into())
+            .collect::<Vec<Element<Message>>>());
+
+        container(
+            col![
+                row![
+                    text("Topics").width(Length::Fill),
+                    button("X").on_press(Message::HideTopics)
+                ]
+                .height(50)
+                .align_y(iced::Alignment::Center),
+                topic_list,
+                edit,
+                collisions,
+            ]
+            .spacing(10),
+        )
+        .width(750)
+        .padding(10)
+        .style(|_theme| container::Style {
+            text_color: Some(iced::Color::from_rgb8(255, 224, 181)),
+            background: Some(iced::Background::Color(Color::from_rgb8(25, 19, 19))),
+            border: iced::Border::default(),
+            shadow: iced::Shadow::default(),
+        })
+        .into()
+    }
+
+    pub fn view_existing_topic(&self) -> Element<Message> {
+        let file = self.existing_topic.clone().unwrap_or_default();
+        container(
+            col![
+                text(format!(
+                    "{} already exists. Creating it again would erase its captures.",
+                    file
+                )),
+                row![
+                    button("Open it").on_press(Message::OpenExistingTopic),
+                    button("Cancel").on_press(Message::HideExistingTopic),
+                ]
+                .spacing(10),
+            ]
+            .spacing(10),
+        )
+        .width(500)
+        .padding(10)
+        .style(|_theme| container::Style {
+            text_color: Some(iced::Color::from_rgb8(255, 224, 181)),
+            background: Some(iced::Background::Color(Color::from_rgb8(25, 19, 19))),
+            border: iced::Border::default(),
+            shadow: iced::Shadow::default(),
+        })
+        .into()
+    }
+
+    fn view_tag_filter(&self) -> Element<Message> {
+        let tags: Vec<String> = self.all_tags().into_iter().map(|(tag, _)| tag).collect();
+        row![
+            pick_list(tags, self.capture.tag_filter.clone(), |tag| {
+                Message::TagFilterChanged(Some(tag))
+            })
+            .placeholder("Tag.."),
+            button("X").on_press(Message::TagFilterChanged(None)),
+        ]
+        .into()
+    }
+
+    /// Looks the capture search up in capture bodies. Results for an outdated search are dropped.
+    pub fn search_bodies(&mut self) -> Task<Message> {
+        let text = Query::parse(&self.capture.search).body_text();
+        if text.is_empty() {
+            self.capture.body_results = Vec::new();
+            return Task::none();
+        }
+        let vault = self.active_vault.name.clone();
+        let search = self.capture.search.clone();
+        Task::perform(
+            index::search(self.vault_root(), text, BODY_RESULTS),
+            move |result| Message::BodySearched(vault.clone(), search.clone(), result),
+        )
+    }
+
+    /// Files matching the editor search, best first.
+    pub fn file_results(&self) -> Vec<(&String, SearchMatch)> {
+        let query = Query::parse(&self.editor.search);
+        search::rank(self.files.iter(), |file| query.match_file(file))
+    }
+
+    /// Renders `value` with the characters at `positions` highlighted.
+    fn highlighted<'a>(value: &'a str, positions: &[usize]) -> Element<'a, Message> {
+        let mut spans = Vec::new();
+        let mut run = String::new();
+        let mut run_matched = false;
+        for (i, c) in value.chars().enumerate() {
+            let matched = positions.binary_search(&i).is_ok();
+            if matched != run_matched && !run.is_empty() {
+                spans.push((std::mem::take(&mut run), run_matched));
+            }
+            run_matched = matched;
+            run.push(c);
+        }
+        if !run.is_empty() {
+            spans.push((run, run_matched));
+        }
+
+        rich_text(
+            spans
+                .into_iter()
+                .map(|(run, matched)| {
+                    if matched {
+                        span(run).color(Color::from_rgb8(255, 120, 90))
+                    } else {
+                        span(run).color(Color::from_rgb8(255, 244, 181))
+                    }
+                })
+                .collect::<Vec<_>>(),
+        )
+        .into()
+    }
+
+    fn view_vault_picker(&self) -> Element<Message> {
+        pick_list(
+            self.vaults.as_slice(),
+            Some(&self.active_vault),
+            Message::SwitchVault,
+        )
+        .placeholder("Vault..")
+        .into()
+    }
+
+    // fn hide_subselect_capture(&mut self) {
+    //     self.is_subselect_capture = false;
+    // }
+
+    pub fn view_capture_sidebar(&self) -> Element<Message> {
+        let mut capture_list = self
+            .capture_results()
+            .into_iter()
+            .map(|(i, found)| {
+                let capture = &self.captures[i];
+                let text_fields = [
+                    (&capture.header.timestamp, &[][..]),
+                    (&capture.header.topic, &found.fields[0][..]),
+                    (&capture.header.subject, &found.fields[1][..]),
+                ]
+                .into_iter()
+                .map(|(field, positions)| {
+                    container(Self::highlighted(field, positions))
+                        .width(Length::Fill)
+                        .padding(5)
+                        .style(|_theme| container::Style {
+                            background: Some(Background::Color(Color::BLACK)),
+                            ..container::Style::default()
+                        })
+                        .into()
+                    // let capture_button = mouse_area(
+                    //     button(row![
+                    //         col(capture
+                    //             .iter()
+                    //             .map(|field| text(field).into())
+                    //             .collect::<Vec<Element<Message>>>()),
+                    //         button("DEL").on_press(Message::DeleteCapture(i))
+                    //     ])
+                    //     .width(750)
+                    //     .on_press(Message::CaptureSelected(i))
+                    //     .style(|_theme, status| match status {
+                    //         button::Status::Hovered => button::Style {
+                    //             background: Some(iced::Background::Color(Color::from_rgb8(25, 19, 19))),
+                    //             text_color: iced::Color::from_rgb8(255, 224, 181),
+                    //             border: iced::Border::default(),
+                    //             shadow: iced::Shadow::default(),
+                    //         },
+                    //         _ => button::Style {
+                    //             background: Some(iced::Background::Color(Color::from_rgb8(15, 9, 9))),
+                    //             text_color: iced::Color::from_rgb8(255, 224, 181),
+                    //             border: iced::Border::default(),
+                    //             shadow: iced::Shadow::default(),
+                    //         },
+                    //     }),
+                    // )
+                    // .on_right_press(Message::SubselectCapture);
+                    //
+                    // capture_button.into()
+                })
+                .collect::<Vec<Element<Message>>>();
 
                 let capture_item = mouse_area(row![col(text_fields)].width(750));
 
@@ -119,12 +730,39 @@ impl Yoink {
             })
             .collect::<Vec<Element<Message>>>();
 
+        if !self.capture.body_results.is_empty() {
+            capture_list.push(text("In capture bodies").into());
+        }
+        for (i, hit) in self.capture.body_results.iter().enumerate() {
+            let header = &hit.record.header;
+            let hit_item = mouse_area(
+                container(col![
+                    text(format!(
+                        "{} {} {}",
+                        header.timestamp, header.topic, header.subject
+                    )),
+                    Self::highlighted(&hit.snippet, &hit.highlights),
+                ])
+                .width(750)
+                .padding(5)
+                .style(|_theme| container::Style {
+                    background: Some(Background::Color(Color::BLACK)),
+                    text_color: Some(Color::from_rgb8(255, 244, 181)),
+                    ..container::Style::default()
+                }),
+            )
+            .on_press(Message::BodyHitSelected(i));
+            capture_list.push(hit_item.into());
+        }
+
         let capture_sidebar = if self.capture_sidebar.is_visible {
             container(
                 col![
                     row![
+                        self.view_vault_picker(),
                         text_input("Capture..", &self.capture.search)
                             .on_input(Message::CaptureSearchChanged),
+                        self.view_tag_filter(),
                         button("X")
                     ]
                     .height(50)
@@ -182,10 +820,16 @@ impl Yoink {
                             }
                         })
                         .height(400),
-                    row![button("Switch").on_press(Message::Edit)]
-                        .width(Shrink)
-                        .height(50)
-                        .align_y(iced::Alignment::Center)
+                    row![
+                        button("Switch").on_press(Message::Edit),
+                        button("Trash").on_press(Message::ViewTrash),
+                        button("Tags").on_press(Message::ViewTags),
+                        button("Topics").on_press(Message::ViewTopics)
+                    ]
+                    .spacing(10)
+                    .width(Shrink)
+                    .height(50)
+                    .align_y(iced::Alignment::Center)
                 ]
                 .height(500)
                 .spacing(10),
@@ -217,6 +861,16 @@ impl Yoink {
 
     pub fn view_capture_pane(&self) -> Element<Message> {
         let submit_button = self.view_submit_button();
+        let tag_suggestions = row(self
+            .tag_suggestions()
+            .into_iter()
+            .map(|tag| {
+                button(text(format!("#{}", tag)))
+                    .on_press(Message::CaptureTagSuggested(tag))
+                    .into()
+            })
+            .collect::<Vec<Element<Message>>>())
+        .spacing(5);
         let capture_pane = if self.capture_pane.is_visible {
             container(
                 col![
@@ -225,8 +879,12 @@ impl Yoink {
                         .on_input(Message::CaptureTopicChanged),
                     text_input("Subject..", &self.capture.form_subject)
                         .on_input(Message::CaptureSubjectChanged),
+                    text_input("Tags..", &self.capture.form_tags)
+                        .on_input(Message::CaptureTagsChanged),
+                    tag_suggestions,
                     text_editor(&self.capture.form_content)
-                        .on_action(Message::CaptureFormContentChanged),
+                        .on_action(Message::CaptureFormContentChanged)
+                        .highlight_with::<Highlighter>((), highlighter::format),
                     submit_button,
                 ]
                 .max_width(400)
@@ -261,11 +919,11 @@ impl Yoink {
 
     pub fn view_editor_sidebar(&self) -> Element<Message> {
         let editor_list = self
-            .files
-            .iter()
-            .map(|file| {
+            .file_results()
+            .into_iter()
+            .map(|(file, found)| {
                 let file_button = button(row![
-                    text(file.clone()),
+                    Self::highlighted(file, &found.fields[0]),
                     button("DEL").on_press(Message::DeleteFile(file.clone()))
                 ])
                 .width(Length::Fill)
@@ -293,9 +951,9 @@ impl Yoink {
         let editor_sidebar = if self.editor_sidebar.is_visible {
             container(
                 col![
+                    self.view_vault_picker(),
                     row![
-                        // TODO: to FileSearchChanged
-                        text_input("Editor..", &self.capture.search)
+                        text_input("Editor..", &self.editor.search)
                             .on_input(Message::FileSearchChanged),
                         button("X")
                     ]
@@ -396,17 +1054,51 @@ impl Yoink {
             .clip(false)
             .on_press(Message::Ignore)
             .style(|_theme, _status| CustomStyle::default());
+        let editor: Element<Message> = if self.editor.preview_mode == PreviewMode::Only {
+            self.view_preview_pane()
+        } else {
+            text_editor(&self.editor.editor_content)
+                .on_action(Message::EditorContentChanged)
+                .highlight_with::<Highlighter>((), highlighter::format)
+                .height(Length::Fill)
+                .padding(10)
+                .into()
+        };
         let editor_pane = if self.editor_pane.is_visible {
             container(col![
-                row![text(self.capture.current_capture.clone()),].align_y(iced::Alignment::Center),
-                text_editor(&self.editor.editor_content)
-                    .on_action(Message::EditorContentChanged)
-                    .height(Length::Fill)
-                    .padding(10),
+                row![text(match (&self.opened_file, &self.opened_preamble) {
+                    (Some(file), _) => file.clone(),
+                    (None, Some(file)) => format!("{} preamble", file),
+                    (None, None) => self.capture.current_capture.clone(),
+                }),]
+                .align_y(iced::Alignment::Center),
+                text(self.editor.conflict.clone().unwrap_or_default())
+                    .color(Color::from_rgb8(255, 182, 182)),
+                self.view_broken_headers(),
+                editor,
                 row![
-                    button("submit file").on_press(Message::UpdateFile),
+                    text_input("Move to topic..", &self.capture.move_topic)
+                        .on_input(Message::MoveTopicInput)
+                        .on_submit(Message::MoveCapture),
+                    button("move capture").on_press(Message::MoveCapture),
+                ],
+                row![
+                    button("submit file").on_press(
+                        match (&self.opened_file, &self.opened_preamble) {
+                            (Some(_), _) => Message::UpdateFile,
+                            (None, Some(_)) => Message::UpdatePreamble,
+                            (None, None) => Message::UpdateCapture,
+                        }
+                    ),
                     button("create file").on_press(Message::CreateFile),
                     button("create file").on_press(Message::ViewModalHelper),
+                    button("trash").on_press(Message::ViewTrash),
+                    button("topics").on_press(Message::ViewTopics),
+                    pick_list(
+                        PreviewMode::ALL,
+                        Some(self.editor.preview_mode),
+                        Message::PreviewModeChanged
+                    ),
                     mybutton,
                 ]
             ])
@@ -418,6 +1110,220 @@ impl Yoink {
         col!(editor_pane).into()
     }
 
+    /// Points at the broken capture headers that block saving the file, with a repair offer.
+    pub fn view_broken_headers(&self) -> Element<Message> {
+        if self.editor.broken_headers.is_empty() {
+            return row![].into();
+        }
+        let lines: Vec<String> = self
+            .editor
+            .broken_headers
+            .iter()
+            .map(usize::to_string)
+            .collect();
+        row![
+            text(format!(
+                "Broken capture headers on lines {}; the file cannot be saved until they are fixed.",
+                lines.join(", ")
+            ))
+            .color(Color::from_rgb8(255, 182, 182)),
+            button("repair headers").on_press(Message::RepairHeaders),
+        ]
+        .spacing(10)
+        .align_y(iced::Alignment::Center)
+        .into()
+    }
+
+    pub fn view_preview_pane(&self) -> Element<Message> {
+        let blocks = self
+            .editor
+            .preview
+            .blocks()
+            .map(Self::view_block)
+            .collect::<Vec<Element<Message>>>();
+
+        container(scrollable(col(blocks).spacing(12).padding(10)).height(Length::Fill))
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .padding(10)
+            .style(|_theme| container::Style {
+                text_color: Some(iced::Color::from_rgb8(255, 224, 181)),
+                background: Some(iced::Background::Color(Color::from_rgb8(25, 19, 19))),
+                border: iced::Border::default(),
+                shadow: iced::Shadow::default(),
+            })
+            .into()
+    }
+
+    fn view_block(block: &Block) -> Element<Message> {
+        match block {
+            Block::Divider(header) => {
+                let tags = header
+                    .tags
+                    .iter()
+                    .map(|tag| format!("#{}", tag))
+                    .collect::<Vec<String>>()
+                    .join(" ");
+                container(
+                    row![
+                        text(header.subject.as_str()).font(Font {
+                            weight: font::Weight::Bold,
+                            ..Font::DEFAULT
+                        }),
+                        text(header.topic.as_str()),
+                        text(header.timestamp.as_str()),
+                        text(tags),
+                    ]
+                    .spacing(15),
+                )
+                .width(Length::Fill)
+                .padding(6)
+                .style(|_theme| container::Style {
+                    background: Some(Background::Color(Color::BLACK)),
+                    text_color: Some(Color::from_rgb8(255, 120, 90)),
+                    border: Border {
+                        color: Color::from_rgb8(180, 60, 60),
+                        width: 1.0,
+                        radius: 5.0.into(),
+                    },
+                    ..container::Style::default()
+                })
+                .into()
+            }
+            Block::Heading(level, runs) => {
+                let size = match level {
+                    1 => 28,
+                    2 => 24,
+                    3 => 20,
+                    _ => 17,
+                };
+                Self::view_runs(runs, size, true)
+            }
+            Block::Paragraph(runs) => Self::view_runs(runs, 15, false),
+            Block::Quote(blocks) => {
+                container(col(blocks.iter().map(Self::view_block).collect::<Vec<_>>()).spacing(8))
+                    .width(Length::Fill)
+                    .padding(Padding::from([4, 12]))
+                    .style(|_theme| container::Style {
+                        background: Some(Background::Color(Color::from_rgb8(35, 29, 29))),
+                        text_color: Some(Color::from_rgb8(205, 180, 150)),
+                        ..container::Style::default()
+                    })
+                    .into()
+            }
+            Block::List { start, items } => col(items
+                .iter()
+                .enumerate()
+                .map(|(i, item)| {
+                    let marker = match start {
+                        Some(start) => format!("{}.", start + i as u64),
+                        None => "•".to_string(),
+                    };
+                    row![
+                        text(marker).width(28),
+                        col(item.iter().map(Self::view_block).collect::<Vec<_>>()).spacing(4),
+                    ]
+                    .into()
+                })
+                .collect::<Vec<_>>())
+            .spacing(4)
+            .into(),
+            Block::Code { text: code, .. } => {
+                container(text(code.as_str()).font(Font::MONOSPACE).size(14))
+                    .width(Length::Fill)
+                    .padding(8)
+                    .style(|_theme| container::Style {
+                        background: Some(Background::Color(Color::from_rgb8(15, 9, 9))),
+                        ..container::Style::default()
+                    })
+                    .into()
+            }
+            Block::Table {
+                alignments,
+                header,
+                rows,
+            } => container(col(std::iter::once(Self::view_table_row(
+                header, alignments, true,
+            ))
+            .chain(
+                rows.iter()
+                    .map(|cells| Self::view_table_row(cells, alignments, false)),
+            )
+            .collect::<Vec<Element<Message>>>()))
+            .width(Length::Fill)
+            .style(|_theme| container::Style {
+                border: Border {
+                    color: Color::from_rgb8(120, 30, 30),
+                    width: 1.0,
+                    radius: 0.0.into(),
+                },
+                ..container::Style::default()
+            })
+            .into(),
+            Block::Rule => horizontal_rule(1).into(),
+        }
+    }
+
+    fn view_table_row<'a>(
+        cells: &'a [Vec<Run>],
+        alignments: &[Alignment],
+        bold: bool,
+    ) -> Element<'a, Message> {
+        row(cells
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| {
+                let align_x = match alignments.get(i) {
+                    Some(Alignment::Center) => iced::alignment::Horizontal::Center,
+                    Some(Alignment::Right) => iced::alignment::Horizontal::Right,
+                    _ => iced::alignment::Horizontal::Left,
+                };
+                container(Self::view_runs(cell, 15, bold))
+                    .width(Length::FillPortion(1))
+                    .align_x(align_x)
+                    .padding(4)
+                    .into()
+            })
+            .collect::<Vec<_>>())
+        .into()
+    }
+
+    /// Renders styled runs as one rich text. Links are clickable.
+    fn view_runs(runs: &[Run], size: u16, bold: bool) -> Element<Message> {
+        rich_text(
+            runs.iter()
+                .map(|run| {
+                    let mut font = if run.code {
+                        Font::MONOSPACE
+                    } else {
+                        Font::DEFAULT
+                    };
+                    if bold || run.strong {
+                        font.weight = font::Weight::Bold;
+                    }
+                    if run.emphasis {
+                        font.style = font::Style::Italic;
+                    }
+                    let mut styled = span(run.text.as_str())
+                        .font(font)
+                        .size(size)
+                        .strikethrough(run.strikethrough);
+                    if run.code {
+                        styled = styled.background(Color::from_rgb8(15, 9, 9));
+                    }
+                    if let Some(url) = &run.link {
+                        styled = styled
+                            .color(Color::from_rgb8(255, 160, 120))
+                            .underline(true)
+                            .link(Message::LinkClicked(url.clone()));
+                    }
+                    styled
+                })
+                .collect::<Vec<_>>(),
+        )
+        .into()
+    }
+
     fn view_submit_button(&self) -> Element<Message> {
         let mut submit_button = button("Submit").style(|_theme, status| match status {
             button::Status::Hovered => button::Style {